@@ -24,6 +24,11 @@ fn main() -> ! {
     let mut porta = p.GPIO_PORTA.split(&sc.power_control);
 
     let mut eeprom = Eeprom::new(p.EEPROM, &sc.power_control);
+    if eeprom.needs_recovery() {
+        eeprom
+            .recover(&sc.power_control)
+            .expect("EEPROM could not be recovered");
+    }
 
     match eeprom_test_all(&mut eeprom) {
         Ok(_) => {
@@ -0,0 +1,406 @@
+//! Common firmware update code for TM4C123 and TM4C129
+//!
+//! Firmware images are stored in slots: page aligned regions of flash, each
+//! holding an image (starting with its vector table) and ending with a
+//! 16-byte trailer that records the image length, CRC and a sequence number.
+//! With two slots, a new image can be written to one while the other is
+//! running, and the newest valid image is the one to boot.
+
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+pub use crate::crc::Crc32;
+
+/// Marks a slot trailer as written
+pub const TRAILER_MAGIC: u32 = 0x5443_4D34;
+
+/// The size of the trailer at the end of each slot, in bytes
+pub const TRAILER_SIZE: u32 = 16;
+
+/// Firmware update error
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error<E> {
+    /// The flash driver returned an error
+    Flash(E),
+    /// The image does not fit in the slot
+    TooLarge,
+    /// The slot is not aligned to erase pages, or data was written after a
+    /// chunk whose length was not a multiple of the write size
+    NotAligned,
+    /// The image read back from flash does not have the expected CRC
+    Crc,
+    /// The slot that would be erased holds the running firmware
+    Running,
+}
+
+/// A valid image found in a slot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Image {
+    /// The slot holding the image. The image's vector table is at the start
+    /// of the slot.
+    pub slot: Slot,
+    /// The length of the image, in bytes
+    pub length: u32,
+    /// The CRC-32 of the image
+    pub crc: u32,
+    /// The sequence number the image was written with. Higher is newer.
+    pub sequence: u32,
+}
+
+/// A region of flash that holds one firmware image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slot {
+    /// The address of the start of the slot, which must be aligned to an
+    /// erase page. Images are started by relocating the vector table here,
+    /// so it must also be aligned to 1 KiB.
+    pub start: u32,
+    /// The size of the slot in bytes, which must be a multiple of the erase
+    /// page size
+    pub size: u32,
+}
+
+impl Slot {
+    /// Describes a slot
+    pub const fn new(start: u32, size: u32) -> Slot {
+        Slot { start, size }
+    }
+
+    /// Returns the largest image the slot can hold, in bytes
+    pub fn capacity(&self) -> u32 {
+        self.size.saturating_sub(TRAILER_SIZE)
+    }
+
+    /// Returns whether `address` is inside the slot
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.start && address - self.start < self.size
+    }
+
+    /// Computes the CRC of the first `length` bytes of the slot
+    fn crc<F>(&self, flash: &mut F, length: u32) -> Result<u32, F::Error>
+    where
+        F: ReadNorFlash,
+    {
+        let mut crc = Crc32::new();
+        let mut buffer = [0u8; 64];
+        let mut offset = 0;
+        while offset < length {
+            let count = (length - offset).min(buffer.len() as u32) as usize;
+            // Reads must be a multiple of the read size, and the trailer
+            // means there is always room to round up
+            let padded = count + (F::READ_SIZE - count % F::READ_SIZE) % F::READ_SIZE;
+            flash.read(self.start + offset, &mut buffer[..padded])?;
+            crc.update(&buffer[..count]);
+            offset += count as u32;
+        }
+        Ok(crc.finish())
+    }
+
+    /// Returns the image in the slot, if there is one and its CRC is correct
+    pub fn image<F>(&self, flash: &mut F) -> Result<Option<Image>, F::Error>
+    where
+        F: ReadNorFlash,
+    {
+        let mut trailer = [0u8; TRAILER_SIZE as usize];
+        flash.read(self.start + self.capacity(), &mut trailer)?;
+        let word = |i: usize| {
+            u32::from_le_bytes([
+                trailer[i * 4],
+                trailer[i * 4 + 1],
+                trailer[i * 4 + 2],
+                trailer[i * 4 + 3],
+            ])
+        };
+        let (magic, length, crc, sequence) = (word(0), word(1), word(2), word(3));
+        if magic != TRAILER_MAGIC || length > self.capacity() {
+            return Ok(None);
+        }
+        if self.crc(flash, length)? != crc {
+            return Ok(None);
+        }
+        Ok(Some(Image {
+            slot: *self,
+            length,
+            crc,
+            sequence,
+        }))
+    }
+
+    /// Erases the slot, ready for a new image to be written with
+    /// `sequence` as its sequence number
+    pub fn begin_update<'a, F>(
+        &self,
+        flash: &'a mut F,
+        sequence: u32,
+    ) -> Result<Updater<'a, F>, Error<F::Error>>
+    where
+        F: NorFlash,
+    {
+        // Erase pages are a power of two in size
+        let page = F::ERASE_SIZE as u32;
+        if (self.start | self.size) & (page - 1) != 0 || self.size < page {
+            return Err(Error::NotAligned);
+        }
+        flash
+            .erase(self.start, self.start + self.size)
+            .map_err(Error::Flash)?;
+        Ok(Updater {
+            flash,
+            slot: *self,
+            sequence,
+            length: 0,
+            padded: false,
+        })
+    }
+}
+
+/// A pair of slots, for A/B firmware updates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slots {
+    /// The first slot
+    pub a: Slot,
+    /// The second slot
+    pub b: Slot,
+}
+
+impl Slots {
+    /// Returns the newest valid image in either slot
+    pub fn newest<F>(&self, flash: &mut F) -> Result<Option<Image>, F::Error>
+    where
+        F: ReadNorFlash,
+    {
+        let a = self.a.image(flash)?;
+        let b = self.b.image(flash)?;
+        Ok(match (a, b) {
+            (Some(a), Some(b)) if b.sequence > a.sequence => Some(b),
+            (Some(a), _) => Some(a),
+            (None, b) => b,
+        })
+    }
+
+    /// Erases the slot that does not hold the running firmware, ready for a
+    /// new image that will then be the newest.
+    ///
+    /// `running` is an address in the running firmware, such as its vector
+    /// table (see `vector_table`). If it is in neither slot (for example, a
+    /// bootloader is running), the slot that does not hold the newest image
+    /// is erased. The slot holding `running` is never erased.
+    pub fn begin_update<'a, F>(
+        &self,
+        flash: &'a mut F,
+        running: u32,
+    ) -> Result<Updater<'a, F>, Error<F::Error>>
+    where
+        F: NorFlash,
+    {
+        let newest = self.newest(flash).map_err(Error::Flash)?;
+        let slot = if self.a.contains(running) {
+            self.b
+        } else if self.b.contains(running) {
+            self.a
+        } else {
+            match newest {
+                Some(image) if image.slot == self.a => self.b,
+                _ => self.a,
+            }
+        };
+        if slot.contains(running) {
+            return Err(Error::Running);
+        }
+        let sequence = newest.map_or(0, |image| image.sequence.wrapping_add(1));
+        slot.begin_update(flash, sequence)
+    }
+}
+
+/// Writes a new image into an erased slot
+pub struct Updater<'a, F> {
+    flash: &'a mut F,
+    slot: Slot,
+    sequence: u32,
+    length: u32,
+    padded: bool,
+}
+
+impl<'a, F> Updater<'a, F>
+where
+    F: NorFlash,
+{
+    /// Returns the slot being written
+    pub fn slot(&self) -> Slot {
+        self.slot
+    }
+
+    /// Returns the number of bytes written so far
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Appends data to the image. Every chunk except the last must be a
+    /// multiple of the flash write size. The last is padded with 0xFF, which
+    /// is not part of the image.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error<F::Error>> {
+        if self.padded {
+            return Err(Error::NotAligned);
+        }
+        if data.len() as u32 > self.slot.capacity() - self.length {
+            return Err(Error::TooLarge);
+        }
+        let aligned = data.len() / F::WRITE_SIZE * F::WRITE_SIZE;
+        self.flash
+            .write(self.slot.start + self.length, &data[..aligned])
+            .map_err(Error::Flash)?;
+        self.length += aligned as u32;
+
+        let rest = &data[aligned..];
+        if !rest.is_empty() {
+            let mut buffer = [0xFFu8; 64];
+            if F::WRITE_SIZE > buffer.len() {
+                return Err(Error::NotAligned);
+            }
+            buffer[..rest.len()].copy_from_slice(rest);
+            self.flash
+                .write(self.slot.start + self.length, &buffer[..F::WRITE_SIZE])
+                .map_err(Error::Flash)?;
+            self.length += rest.len() as u32;
+            self.padded = true;
+        }
+        Ok(())
+    }
+
+    /// Reads the image back and checks it against the CRC-32 of the whole
+    /// image, as supplied with it. If it matches, the trailer is written,
+    /// which makes the image valid.
+    pub fn finish(self, crc: u32) -> Result<Image, Error<F::Error>> {
+        if self
+            .slot
+            .crc(self.flash, self.length)
+            .map_err(Error::Flash)?
+            != crc
+        {
+            return Err(Error::Crc);
+        }
+        let mut trailer = [0u8; TRAILER_SIZE as usize];
+        let words = [TRAILER_MAGIC, self.length, crc, self.sequence];
+        for (bytes, word) in trailer.chunks_exact_mut(4).zip(words.iter()) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        self.flash
+            .write(self.slot.start + self.slot.capacity(), &trailer)
+            .map_err(Error::Flash)?;
+        Ok(Image {
+            slot: self.slot,
+            length: self.length,
+            crc,
+            sequence: self.sequence,
+        })
+    }
+}
+
+/// Which interface the ROM bootloader listens on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RomInterface {
+    /// UART0
+    Uart,
+    /// SSI0
+    Ssi,
+    /// I2C0
+    I2c,
+    /// USB0, as a DFU device. The descriptor information is
+    /// `USB_DESCRIPTOR_INFO` from TivaWare's `bl_usbfuncs.h`; a null pointer
+    /// uses the ROM's defaults.
+    Usb(*const u8),
+}
+
+#[macro_export]
+/// Implements the parts of the firmware update support that transfer
+/// control to other code
+///
+/// The invoking module must have the chip's `rom` module in scope.
+macro_rules! boot_hal {
+    () => {
+        /// Reads a word of memory, which may be at address zero
+        fn read_word(address: u32) -> u32 {
+            if address == 0 {
+                // Flash starts at address zero, which can't be read through
+                // a Rust pointer
+                let value: u32;
+                unsafe {
+                    core::arch::asm!(
+                        "ldr {0}, [{1}]",
+                        out(reg) value,
+                        in(reg) 0u32,
+                        options(nostack, readonly, preserves_flags),
+                    )
+                };
+                value
+            } else {
+                unsafe { core::ptr::read_volatile(address as *const u32) }
+            }
+        }
+
+        /// Stops the SysTick timer and disables and clears every interrupt,
+        /// so the next program starts in a state close to reset
+        fn quiesce() {
+            cortex_m::interrupt::disable();
+            unsafe {
+                let syst = &*cortex_m::peripheral::SYST::PTR;
+                syst.csr.write(0);
+                let nvic = &*cortex_m::peripheral::NVIC::PTR;
+                for i in 0..nvic.icer.len() {
+                    nvic.icer[i].write(0xFFFF_FFFF);
+                    nvic.icpr[i].write(0xFFFF_FFFF);
+                }
+            }
+        }
+
+        /// Starts the program whose vector table is at `vector_table`, such
+        /// as a slot holding a valid image, or a bootloader at address zero.
+        ///
+        /// The vector table is relocated (VTABLE), every interrupt is
+        /// disabled and cleared and SysTick is stopped, then the stack
+        /// pointer and reset handler are loaded from the vector table.
+        ///
+        /// # Safety
+        ///
+        /// There must be a valid vector table at `vector_table`, aligned to
+        /// 1 KiB. Peripherals are left running, so the program started must
+        /// cope with them not being at their reset state.
+        pub unsafe fn jump_to(vector_table: u32) -> ! {
+            quiesce();
+            let stack = read_word(vector_table);
+            let reset = read_word(vector_table + 4);
+            (*cortex_m::peripheral::SCB::PTR).vtor.write(vector_table);
+            cortex_m::interrupt::enable();
+            cortex_m::asm::bootstrap(stack as *const u32, reset as *const u32)
+        }
+
+        /// Returns the address of the running vector table (VTABLE), for
+        /// `Slots::begin_update`
+        pub fn vector_table() -> u32 {
+            unsafe { (*cortex_m::peripheral::SCB::PTR).vtor.read() }
+        }
+
+        /// Starts the image, as `jump_to`
+        ///
+        /// # Safety
+        ///
+        /// The image must have been built to run from its slot.
+        pub unsafe fn boot(image: &Image) -> ! {
+            jump_to(image.slot.start)
+        }
+
+        /// Enters the ROM serial bootloader, which waits for a new image on
+        /// the given interface and then resets the chip.
+        ///
+        /// The bootloader does not set up the interface, so the peripheral
+        /// and its pins must already be configured (for UART, including the
+        /// baud rate, and for USB, the PLL and the USB0DP/USB0DM pins).
+        pub fn enter_rom_bootloader(interface: RomInterface) -> ! {
+            quiesce();
+            match interface {
+                RomInterface::Uart => rom::update_uart(),
+                RomInterface::Ssi => rom::update_ssi(),
+                RomInterface::I2c => rom::update_i2c(),
+                RomInterface::Usb(descriptor_info) => rom::update_usb(descriptor_info),
+            }
+        }
+    };
+}
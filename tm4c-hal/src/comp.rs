@@ -0,0 +1,275 @@
+//! Common analog comparator code for TM4C123 and TM4C129
+
+/// Which half of the internal reference ladder is used
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LadderRange {
+    /// The upper range, which does not reach 0 V (ACREFCTL RNG clear)
+    High,
+    /// The lower range, starting at 0 V (ACREFCTL RNG set)
+    Low,
+}
+
+/// When a comparator raises its interrupt or ADC trigger
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sense {
+    /// While the output is low
+    LevelLow,
+    /// While the output is high
+    LevelHigh,
+    /// When the output falls
+    Falling,
+    /// When the output rises
+    Rising,
+    /// When the output changes
+    Both,
+}
+
+impl Sense {
+    /// Returns the value for the ISEN/TSEN field and the ISLVAL/TSLVAL bit
+    pub fn bits(self) -> (u8, bool) {
+        match self {
+            Sense::LevelLow => (0, false),
+            Sense::LevelHigh => (0, true),
+            Sense::Falling => (1, false),
+            Sense::Rising => (2, false),
+            Sense::Both => (3, false),
+        }
+    }
+}
+
+#[macro_export]
+/// Implements the pin traits for an analog comparator
+macro_rules! comp_pins {
+    ($Cn:ident,
+        plus: [$(($($plusgpio: ident)::*, $asrcp: expr)),*],
+        minus: [$($($minusgpio: ident)::*),*],
+        output: [$(($($outgpio: ident)::*, $outaf: ident)),*],
+    ) => {
+        $(
+            impl PositiveInput<$Cn> for $($plusgpio)::*<Tristate> {
+                fn asrcp(&self) -> u8 {
+                    $asrcp
+                }
+            }
+        )*
+
+        $(
+            impl NegativeInput<$Cn> for $($minusgpio)::*<Tristate> {}
+        )*
+
+        $(
+            impl<T> OutputPin<$Cn> for $($outgpio)::*<AlternateFunction<$outaf, T>>
+            where
+                T: OutputMode,
+            {}
+        )*
+    }
+}
+
+#[macro_export]
+/// Implements the analog comparator driver for a chip
+///
+/// The invoking module must provide the pin traits `PositiveInput<C>`,
+/// `NegativeInput<C>` and `OutputPin<C>`.
+macro_rules! comp_hal {
+    ($COMP:ident, [$($Cn:ident: ($compn:ident, $acctl:ident, $acstat:ident, $i:expr),)+]) => {
+        /// Extension trait to split the analog comparator peripheral
+        pub trait CompExt {
+            /// The parts to split the peripheral into
+            type Parts;
+
+            /// Powers up the analog comparators and splits them up
+            fn split(self, pc: &sysctl::PowerControl) -> Self::Parts;
+        }
+
+        /// Analog comparator parts
+        pub struct Parts {
+            /// The internal reference voltage ladder, shared by all the
+            /// comparators
+            pub reference: Reference,
+            $(
+                /// A comparator
+                pub $compn: Comparator<$Cn, (), ()>,
+            )+
+        }
+
+        impl CompExt for $COMP {
+            type Parts = Parts;
+
+            fn split(self, pc: &sysctl::PowerControl) -> Parts {
+                sysctl::control_power(
+                    pc, sysctl::Domain::AnalogComparator,
+                    sysctl::RunMode::Run, sysctl::PowerState::On);
+                sysctl::reset(pc, sysctl::Domain::AnalogComparator);
+
+                Parts {
+                    reference: Reference { _0: () },
+                    $(
+                        $compn: Comparator { inputs: (), output: (), _comp: PhantomData },
+                    )+
+                }
+            }
+        }
+
+        /// The internal reference voltage ladder (ACREFCTL)
+        pub struct Reference {
+            _0: (),
+        }
+
+        impl Reference {
+            /// Enables the ladder, at the given step (0 to 15) of the given
+            /// range. Comparators use it when their positive input is
+            /// `InternalReference`.
+            pub fn enable(&mut self, range: LadderRange, step: u8) {
+                let p = unsafe { &*$COMP::ptr() };
+                p.acrefctl.write(|w| unsafe {
+                    w.vref().bits(step.min(15));
+                    w.rng().bit(range == LadderRange::Low);
+                    w.en().set_bit()
+                });
+            }
+
+            /// Disables the ladder, to save power
+            pub fn disable(&mut self) {
+                let p = unsafe { &*$COMP::ptr() };
+                p.acrefctl.write(|w| unsafe { w.bits(0) });
+            }
+        }
+
+        /// Positive input from the internal reference ladder
+        pub struct InternalReference;
+
+        impl Sealed for InternalReference {}
+
+        $(
+            impl PositiveInput<$Cn> for InternalReference {
+                fn asrcp(&self) -> u8 {
+                    2
+                }
+            }
+        )+
+
+        /// One analog comparator
+        pub struct Comparator<C, INPUTS, OUTPUT> {
+            inputs: INPUTS,
+            output: OUTPUT,
+            _comp: PhantomData<C>,
+        }
+
+        $(
+            impl Comparator<$Cn, (), ()> {
+                /// Connects the comparator inputs. The output is high while
+                /// the positive input is above the negative input (unless it
+                /// is inverted).
+                pub fn connect<PLUS, MINUS>(
+                    self,
+                    plus: PLUS,
+                    minus: MINUS,
+                ) -> Comparator<$Cn, (PLUS, MINUS), ()>
+                where
+                    PLUS: PositiveInput<$Cn>,
+                    MINUS: NegativeInput<$Cn>,
+                {
+                    let p = unsafe { &*$COMP::ptr() };
+                    p.$acctl.write(|w| unsafe { w.asrcp().bits(plus.asrcp()) });
+                    Comparator { inputs: (plus, minus), output: (), _comp: PhantomData }
+                }
+            }
+
+            impl<PLUS, MINUS> Comparator<$Cn, (PLUS, MINUS), ()> {
+                /// Drives the comparator output onto a pin
+                pub fn route_output<PIN>(self, pin: PIN) -> Comparator<$Cn, (PLUS, MINUS), PIN>
+                where
+                    PIN: OutputPin<$Cn>,
+                {
+                    Comparator { inputs: self.inputs, output: pin, _comp: PhantomData }
+                }
+
+                /// Disconnects the inputs and releases the pins
+                pub fn disconnect(self) -> (Comparator<$Cn, (), ()>, (PLUS, MINUS)) {
+                    let p = unsafe { &*$COMP::ptr() };
+                    p.$acctl.write(|w| unsafe { w.bits(0) });
+                    cortex_m::interrupt::free(|_| {
+                        p.acinten.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << $i)) });
+                    });
+                    (Comparator { inputs: (), output: (), _comp: PhantomData }, self.inputs)
+                }
+            }
+
+            impl<PLUS, MINUS, PIN> Comparator<$Cn, (PLUS, MINUS), PIN> {
+                /// Stops driving the output pin and releases it
+                pub fn release_output(self) -> (Comparator<$Cn, (PLUS, MINUS), ()>, PIN) {
+                    (Comparator { inputs: self.inputs, output: (), _comp: PhantomData }, self.output)
+                }
+
+                /// Returns the comparator output
+                pub fn output(&self) -> bool {
+                    let p = unsafe { &*$COMP::ptr() };
+                    p.$acstat.read().oval().bit_is_set()
+                }
+
+                /// Sets whether the comparator output is inverted. This also
+                /// affects the output pin, interrupt and ADC trigger.
+                pub fn set_inverted(&mut self, inverted: bool) {
+                    let p = unsafe { &*$COMP::ptr() };
+                    p.$acctl.modify(|_, w| w.cinv().bit(inverted));
+                }
+
+                /// Starts raising the interrupt on the given output condition
+                pub fn listen(&mut self, sense: Sense) {
+                    let p = unsafe { &*$COMP::ptr() };
+                    let (isen, islval) = sense.bits();
+                    p.$acctl.modify(|_, w| unsafe {
+                        w.isen().bits(isen);
+                        w.islval().bit(islval)
+                    });
+                    p.acmis.write(|w| unsafe { w.bits(1 << $i) });
+                    cortex_m::interrupt::free(|_| {
+                        p.acinten.modify(|r, w| unsafe { w.bits(r.bits() | 1 << $i) });
+                    });
+                }
+
+                /// Stops raising the interrupt
+                pub fn unlisten(&mut self) {
+                    let p = unsafe { &*$COMP::ptr() };
+                    cortex_m::interrupt::free(|_| {
+                        p.acinten.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << $i)) });
+                    });
+                }
+
+                /// Returns whether the interrupt condition has occurred since
+                /// it was last cleared, whether or not it is being listened
+                /// for
+                pub fn is_pending(&self) -> bool {
+                    let p = unsafe { &*$COMP::ptr() };
+                    p.acris.read().bits() & 1 << $i != 0
+                }
+
+                /// Clears the interrupt
+                pub fn clear_interrupt(&mut self) {
+                    let p = unsafe { &*$COMP::ptr() };
+                    p.acmis.write(|w| unsafe { w.bits(1 << $i) });
+                }
+
+                /// Triggers the ADC on the given output condition. The ADC
+                /// sample sequencer must also be set to trigger from this
+                /// comparator.
+                pub fn enable_adc_trigger(&mut self, sense: Sense) {
+                    let p = unsafe { &*$COMP::ptr() };
+                    let (tsen, tslval) = sense.bits();
+                    p.$acctl.modify(|_, w| unsafe {
+                        w.tsen().bits(tsen);
+                        w.tslval().bit(tslval);
+                        w.toen().set_bit()
+                    });
+                }
+
+                /// Stops triggering the ADC
+                pub fn disable_adc_trigger(&mut self) {
+                    let p = unsafe { &*$COMP::ptr() };
+                    p.$acctl.modify(|_, w| w.toen().clear_bit());
+                }
+            }
+        )+
+    }
+}
@@ -0,0 +1,50 @@
+//! Checksums shared by the firmware update and key/value store code
+
+/// Computes CRC-32, as used by Ethernet and zlib
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    /// Starts a new CRC
+    pub fn new() -> Crc32 {
+        Crc32(0xFFFF_FFFF)
+    }
+
+    /// Adds bytes to the CRC
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u32::from(*byte);
+            for _ in 0..8 {
+                self.0 = if self.0 & 1 != 0 {
+                    (self.0 >> 1) ^ 0xEDB8_8320
+                } else {
+                    self.0 >> 1
+                };
+            }
+        }
+    }
+
+    /// Returns the CRC of the bytes added so far
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        let mut crc = Crc32::new();
+        crc.update(b"12345");
+        crc.update(b"6789");
+        assert_eq!(crc.finish(), 0xCBF4_3926);
+    }
+}
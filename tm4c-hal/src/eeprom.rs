@@ -12,6 +12,9 @@
 //! This flexibility comes at the cost of efficiency, as the
 //! datasheet calls for at least 4 cycles of delay after setting the EEBLOCK
 //! register.
+//!
+//! For bulk transfers of whole words, `read_words` and `write_words` do use
+//! EERDWRINC, setting the block and offset only once per block.
 
 /// Possible errors for the Flash memory module
 #[derive(Debug, PartialEq)]
@@ -30,6 +33,24 @@ pub enum EepromError {
     ReadWouldOverflow,
     /// Requesting to read more data than the provided buffer can hold
     ReadBufferTooSmall,
+    /// A password must be one to three words, and not all ones
+    InvalidPassword,
+    /// The block is still locked, as the password was wrong
+    Locked,
+    /// A write was refused, because the block is locked or read only
+    /// (EEDONE NOPERM)
+    NoPermission,
+    /// A write was attempted while the EEPROM was busy (EEDONE WRBUSY)
+    WriteBusy,
+    /// A write failed because the supply voltage was out of range (EEDONE
+    /// INVPL)
+    InvalidVoltage,
+    /// An erase needs to be retried, as it was interrupted by power loss or
+    /// the supply was unstable (EESUPP ERETRY). See `Eeprom::recover`.
+    EraseRetry,
+    /// A program needs to be retried, as it was interrupted by power loss or
+    /// the supply was unstable (EESUPP PRETRY). See `Eeprom::recover`.
+    ProgramRetry,
 }
 
 impl core::fmt::Display for EepromError {
@@ -46,6 +67,15 @@ impl core::fmt::Display for EepromError {
                 write!(f, "Reading this data would overflow the EEPROM")
             }
             EepromError::ReadBufferTooSmall => write!(f, "Allocated buffer too small for reading"),
+            EepromError::InvalidPassword => write!(f, "Password is not one to three words"),
+            EepromError::Locked => write!(f, "Block is still locked"),
+            EepromError::NoPermission => write!(f, "No permission to write to the block"),
+            EepromError::WriteBusy => write!(f, "Write attempted while the EEPROM was busy"),
+            EepromError::InvalidVoltage => write!(f, "Supply voltage invalid for writing"),
+            EepromError::EraseRetry => write!(f, "Eeprom ERETRY bit set, erase must be retried"),
+            EepromError::ProgramRetry => {
+                write!(f, "Eeprom PRETRY bit set, program must be retried")
+            }
         }
     }
 }
@@ -92,6 +122,23 @@ impl EepromAddress {
     }
 }
 
+/// Access to an EEPROM block, set in EEPROT
+///
+/// A password, set with `set_password`, makes a block locked at reset until
+/// it is unlocked. A password on block 0 is the master password, which
+/// locks the whole EEPROM.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protection {
+    /// The block is always readable, and is writable unless it is locked.
+    /// This is the default.
+    ReadWrite = 0,
+    /// The block is only readable or writable while unlocked. This only
+    /// means anything for a block with a password.
+    Unlocked = 1,
+    /// The block is never writable. It is readable unless it is locked.
+    ReadOnly = 2,
+}
+
 /// Series of traits to make access blocks easier
 pub trait Blocks {
     /// Returns the blocksize for read / write to the flash
@@ -140,3 +187,728 @@ pub trait Read {
         buffer: &mut [u8],
     ) -> Result<(), EepromError>;
 }
+
+#[macro_export]
+/// Implements the EEPROM driver for a chip with `$num_blocks` blocks of 16
+/// words. `$eehide` is the first EEHIDE register.
+macro_rules! eeprom_hal {
+    ($EEPROM:ident, $num_blocks:expr, $eehide:ident) => {
+        // Number of words in an EEPROM block
+        const EEPROM_BLOCK_SIZE: usize = 16;
+
+        // Number of EEPROM blocks
+        const EEPROM_NUM_BLOCKS: usize = $num_blocks;
+
+        // Total number of bytes in the EEPROM
+        const EEPROM_END_ADDRESS_BYTES: usize = EEPROM_END_ADDRESS_WORDS * BYTES_PER_WORD;
+
+        // Total number of words in the EEPROM
+        const EEPROM_END_ADDRESS_WORDS: usize = EEPROM_NUM_BLOCKS * EEPROM_BLOCK_SIZE;
+
+        // Size of the EEPROM word in bytes
+        const BYTES_PER_WORD: usize = 4;
+
+        /// Eeprom struct
+        pub struct Eeprom {
+            /// Eeprom registers
+            eeprom: $EEPROM,
+        }
+
+        impl Eeprom {
+            /// Configures a new EEPROM struct using the datasheet section 8.2.4.2.
+            /// The EEPROM is returned even if PRETRY or ERETRY in the EESUPP
+            /// register are set during the initialization, so check
+            /// `needs_recovery` before using it, and call `recover` if it
+            /// does. Section 8.2.4.2 explains further:
+            ///
+            /// If the PRETRY or ERETRY bits are set in the EESUPP register, the EEPROM
+            /// was unable to recover its state. If power is stable when this occurs,
+            /// this indicates a fatal error and is likely an indication that the EEPROM
+            /// memory has exceeded its specified lifetime write/erase specification.
+            /// If the supply voltage is unstable when this return code is observed,
+            /// retrying the operation once the voltage is stabilized may clear the
+            /// error.
+            pub fn new(eeprom: $EEPROM, pc: &sysctl::PowerControl) -> Self {
+                let mut final_eeprom = Eeprom { eeprom };
+
+                // See Section 8.2.4.2 EEPROM Initialization and Configuration
+                // in the datasheet:
+
+                // 0. Power on the EEPROM peripheral
+                sysctl::control_power(
+                    pc,
+                    sysctl::Domain::Eeprom,
+                    sysctl::RunMode::Run,
+                    sysctl::PowerState::On,
+                );
+
+                // 1. The datasheet calls for at least a 6 cycle delay before polling
+                // the working register. Need to make sure the loop isn't optimized
+                // out.
+                delay(20);
+
+                // 2. Poll busy
+                final_eeprom.wait();
+
+                // 3. Read PRETRY and ERETRY, leaving the EEPROM for the caller
+                // to recover if either is set. See section 8.2.4.2
+                if final_eeprom.needs_recovery() {
+                    return final_eeprom;
+                }
+
+                // 4 - 7. Software reset, then recheck PRETRY and ERETRY. A
+                // failure here is also reported by `needs_recovery`.
+                let _ = final_eeprom.recover(pc);
+
+                // 8. All done
+                final_eeprom
+            }
+
+            /// Returns an error if EESUPP reports that an erase or program
+            /// needs to be retried
+            fn supply_status(&self) -> Result<(), EepromError> {
+                let eesupp = self.eeprom.eesupp.read();
+                if eesupp.eretry().bit_is_set() {
+                    Err(EepromError::EraseRetry)
+                } else if eesupp.pretry().bit_is_set() {
+                    Err(EepromError::ProgramRetry)
+                } else {
+                    Ok(())
+                }
+            }
+
+            /// Waits for a write to finish, and returns an error if EEDONE
+            /// or EESUPP report that it failed
+            fn write_status(&self) -> Result<(), EepromError> {
+                self.wait();
+                let eedone = self.eeprom.eedone.read();
+                if eedone.noperm().bit_is_set() {
+                    Err(EepromError::NoPermission)
+                } else if eedone.wrbusy().bit_is_set() {
+                    Err(EepromError::WriteBusy)
+                } else if eedone.bits() & (1 << 8) != 0 {
+                    Err(EepromError::InvalidVoltage)
+                } else {
+                    self.supply_status()
+                }
+            }
+
+            /// Returns whether the EEPROM needs recovery, because an erase or
+            /// program was interrupted (EESUPP ERETRY or PRETRY)
+            pub fn needs_recovery(&self) -> bool {
+                self.supply_status().is_err()
+            }
+
+            /// Resets the EEPROM, which makes it retry an interrupted erase or
+            /// program, and checks that it succeeded. This should be done
+            /// once the supply voltage is stable. If it still fails with a
+            /// stable supply, the EEPROM has probably worn out.
+            pub fn recover(&mut self, pc: &sysctl::PowerControl) -> Result<(), EepromError> {
+                // Software reset
+                sysctl::reset(pc, sysctl::Domain::Eeprom);
+
+                // Another delay
+                delay(20);
+
+                // Poll busy
+                self.wait();
+
+                // Recheck PRETRY and ERETRY
+                self.supply_status()
+            }
+
+            /// Set the block register
+            fn set_block(&self, block: usize) -> Result<(), EepromError> {
+                if self.is_busy() {
+                    return Err(EepromError::Busy);
+                }
+
+                if block < EEPROM_NUM_BLOCKS {
+                    unsafe {
+                        self.eeprom.eeblock.write(|w| w.bits(block as u32));
+                    }
+
+                    // Changing blocks requires a small delay, see Section 8.2.4.1 Timing Considerations
+                    delay(4);
+
+                    self.wait();
+
+                    Ok(())
+                } else {
+                    Err(EepromError::BlockOutOfBounds)
+                }
+            }
+
+            /// Set the offset register
+            fn set_offset(&self, offset: usize) -> Result<(), EepromError> {
+                if self.is_busy() {
+                    return Err(EepromError::Busy);
+                }
+
+                if offset < EEPROM_BLOCK_SIZE {
+                    unsafe {
+                        self.eeprom.eeoffset.write(|w| w.bits(offset as u32));
+                    }
+
+                    self.wait();
+
+                    Ok(())
+                } else {
+                    Err(EepromError::OffsetOutOfBounds)
+                }
+            }
+
+            /// Set the block and offset registers
+            fn set_block_and_offset(&self, address: &EepromAddress) -> Result<(), EepromError> {
+                self.wait();
+                self.set_block(address.block())?;
+                self.set_offset(address.offset())?;
+                Ok(())
+            }
+
+            /// Checks if read / writing a certain number of bytes from an address is
+            /// valid. Returns true if EEPROM access is valid, false if there
+            /// are any issues (overflow or invalid address).
+            fn is_access_valid(&self, address: &EepromAddress, length_bytes: usize) -> bool {
+                // Check if the initial address is valid, then check byte length
+                match self.address_to_word_index(&address) {
+                    Ok(start_word_address) => {
+                        return start_word_address * BYTES_PER_WORD + length_bytes
+                            <= EEPROM_END_ADDRESS_BYTES;
+                    }
+                    Err(_) => {
+                        return false;
+                    }
+                }
+            }
+
+            /// Increments the block and offset by 1 word. Will wrap both the offset and
+            /// block to 0 if an increment would cause either to exceed their bounds.
+            ///
+            /// For example:
+            /// * Block 0, Offset, 1 -> Block 0, Offset 2
+            /// * Block 0, Offset, 15 -> Block 1, Offset 0
+            /// * Block 31, Offset, 15 -> Block 0, Offset 0
+            fn increment_offset(
+                &mut self,
+                starting_address: &mut EepromAddress,
+            ) -> Result<(), EepromError> {
+                starting_address.increment(EEPROM_BLOCK_SIZE, EEPROM_NUM_BLOCKS);
+                self.set_block_and_offset(&starting_address)?;
+                Ok(())
+            }
+        }
+
+        impl Eeprom {
+            /// Sets the protection of a block. With `supervisor_only`, the
+            /// block can only be accessed in privileged mode.
+            pub fn set_protection(
+                &mut self,
+                block: usize,
+                protection: Protection,
+                supervisor_only: bool,
+            ) -> Result<(), EepromError> {
+                self.set_block(block)?;
+                let acc = if supervisor_only { 1 << 3 } else { 0 };
+                unsafe {
+                    self.eeprom
+                        .eeprot
+                        .write(|w| w.bits(protection as u32 | acc));
+                }
+                self.write_status()
+            }
+
+            /// Returns the protection of a block, and whether it can only be
+            /// accessed in privileged mode
+            pub fn protection(&self, block: usize) -> Result<(Protection, bool), EepromError> {
+                self.set_block(block)?;
+                let bits = self.eeprom.eeprot.read().bits();
+                let protection = match bits & 0b111 {
+                    1 => Protection::Unlocked,
+                    2 => Protection::ReadOnly,
+                    _ => Protection::ReadWrite,
+                };
+                Ok((protection, bits & (1 << 3) != 0))
+            }
+
+            /// Sets the password of a block, which must be unlocked. The
+            /// password is one to three words, and once set it can only be
+            /// removed by a mass erase. The block stays unlocked until it is
+            /// locked or the chip is reset.
+            pub fn set_password(
+                &mut self,
+                block: usize,
+                password: &[u32],
+            ) -> Result<(), EepromError> {
+                if password.is_empty() || password.len() > 3 || password.iter().all(|w| *w == !0) {
+                    return Err(EepromError::InvalidPassword);
+                }
+                self.set_block(block)?;
+                let eepass = &self.eeprom.eepass0 as *const _ as *mut u32;
+                for (i, word) in password.iter().enumerate() {
+                    unsafe { core::ptr::write_volatile(eepass.add(i), *word) };
+                    self.write_status()?;
+                }
+                Ok(())
+            }
+
+            /// Unlocks a block with its password. Unlocking block 0 with the
+            /// master password unlocks the whole EEPROM.
+            pub fn unlock(&mut self, block: usize, password: &[u32]) -> Result<(), EepromError> {
+                if password.is_empty() || password.len() > 3 {
+                    return Err(EepromError::InvalidPassword);
+                }
+                self.set_block(block)?;
+                // Writing all ones first restarts the unlock sequence, in
+                // case an earlier attempt left it part way through. The
+                // password is then written last word first.
+                unsafe {
+                    self.eeprom.eeunlock.write(|w| w.bits(0xFFFF_FFFF));
+                }
+                for word in password.iter().rev() {
+                    unsafe {
+                        self.eeprom.eeunlock.write(|w| w.bits(*word));
+                    }
+                }
+                if self.eeprom.eeunlock.read().bits() & 1 != 0 {
+                    Ok(())
+                } else {
+                    Err(EepromError::Locked)
+                }
+            }
+
+            /// Locks a block that has a password
+            pub fn lock(&mut self, block: usize) -> Result<(), EepromError> {
+                self.set_block(block)?;
+                unsafe {
+                    self.eeprom.eeunlock.write(|w| w.bits(0xFFFF_FFFF));
+                }
+                Ok(())
+            }
+
+            /// Returns whether a block is locked
+            pub fn is_locked(&self, block: usize) -> Result<bool, EepromError> {
+                self.set_block(block)?;
+                Ok(self.eeprom.eeunlock.read().bits() & 1 == 0)
+            }
+
+            /// Hides a block until the next reset, after which it cannot be
+            /// accessed at all. Block 0 cannot be hidden.
+            pub fn hide(&mut self, block: usize) -> Result<(), EepromError> {
+                if block == 0 || block >= EEPROM_NUM_BLOCKS {
+                    return Err(EepromError::BlockOutOfBounds);
+                }
+                // The EEHIDE registers are consecutive words, one bit per
+                // block
+                let eehide = &self.eeprom.$eehide as *const _ as *mut u32;
+                unsafe {
+                    let reg = eehide.add(block / 32);
+                    let value = core::ptr::read_volatile(reg);
+                    core::ptr::write_volatile(reg, value | 1 << (block % 32));
+                }
+                Ok(())
+            }
+
+            /// Returns whether a block is hidden
+            pub fn is_hidden(&self, block: usize) -> Result<bool, EepromError> {
+                if block >= EEPROM_NUM_BLOCKS {
+                    return Err(EepromError::BlockOutOfBounds);
+                }
+                let eehide = &self.eeprom.$eehide as *const _ as *const u32;
+                let value = unsafe { core::ptr::read_volatile(eehide.add(block / 32)) };
+                Ok(value & 1 << (block % 32) != 0)
+            }
+        }
+
+        impl Busy for Eeprom {
+            fn is_busy(&self) -> bool {
+                self.eeprom.eedone.read().working().bit_is_set()
+            }
+
+            fn wait(&self) {
+                while self.is_busy() == true {}
+            }
+        }
+
+        impl Blocks for Eeprom {
+            fn block_size(&self) -> Result<usize, EepromError> {
+                Ok(EEPROM_BLOCK_SIZE)
+            }
+
+            fn word_index_to_address(
+                &self,
+                word_address: usize,
+            ) -> Result<EepromAddress, EepromError> {
+                if word_address > EEPROM_END_ADDRESS_WORDS {
+                    return Err(EepromError::AddressOutOfBounds);
+                } else {
+                    let block = word_address / EEPROM_BLOCK_SIZE;
+                    let offset = word_address - (block * EEPROM_BLOCK_SIZE);
+                    Ok(EepromAddress::new(block, offset))
+                }
+            }
+
+            fn address_to_word_index(&self, block: &EepromAddress) -> Result<usize, EepromError> {
+                if block.block() > EEPROM_NUM_BLOCKS || block.offset() > EEPROM_BLOCK_SIZE {
+                    return Err(EepromError::BlockOutOfBounds);
+                } else {
+                    return Ok(block.block() * EEPROM_BLOCK_SIZE + block.offset());
+                }
+            }
+        }
+
+        impl Write for Eeprom {
+            fn write(&mut self, address: &EepromAddress, data: &[u8]) -> Result<(), EepromError> {
+                if self.is_busy() {
+                    return Err(EepromError::Busy);
+                }
+
+                // Check if the address is valid and if the data will fit
+                if !self.is_access_valid(address, data.len()) {
+                    return Err(EepromError::WriteWouldOverflow);
+                }
+
+                self.set_block_and_offset(address)?;
+
+                let chunk_iter = data.chunks_exact(4);
+                let leftover_bytes = chunk_iter.remainder();
+                let mut address_copy = *address;
+
+                for chunk in chunk_iter {
+                    let tmp = u32::from_le_bytes(chunk.try_into().unwrap());
+
+                    self.wait();
+
+                    unsafe {
+                        self.eeprom.eerdwr.write(|w| w.bits(tmp));
+                    }
+
+                    self.write_status()?;
+
+                    self.increment_offset(&mut address_copy)?;
+                }
+
+                // Buffer the leftover bytes, if any, and write
+                if leftover_bytes.len() != 0 {
+                    let mut buffer = [0 as u8; 4];
+                    for (i, byte) in leftover_bytes.iter().enumerate() {
+                        buffer[i] = *byte;
+                    }
+
+                    self.wait();
+
+                    unsafe {
+                        self.eeprom
+                            .eerdwr
+                            .write(|w| w.bits(u32::from_le_bytes(buffer)));
+                    }
+
+                    self.write_status()?;
+                }
+
+                self.wait();
+
+                Ok(())
+            }
+        }
+
+        impl Read for Eeprom {
+            fn read(
+                &mut self,
+                address: &EepromAddress,
+                bytes_to_read: usize,
+                buffer: &mut [u8],
+            ) -> Result<(), EepromError> {
+                if self.is_busy() {
+                    return Err(EepromError::Busy);
+                }
+
+                if bytes_to_read > buffer.len() {
+                    return Err(EepromError::ReadBufferTooSmall);
+                }
+
+                if !self.is_access_valid(&address, bytes_to_read) {
+                    return Err(EepromError::ReadWouldOverflow);
+                }
+
+                let num_words = bytes_to_read / BYTES_PER_WORD;
+                let leftover_bytes = bytes_to_read % BYTES_PER_WORD;
+                let mut address_copy = *address;
+
+                self.set_block_and_offset(&address)?;
+
+                let mut byte_offset = 0;
+
+                for _i in 0..num_words {
+                    self.wait();
+
+                    let word_as_bytes = self.eeprom.eerdwr.read().bits().to_le_bytes();
+
+                    self.increment_offset(&mut address_copy)?;
+
+                    for byte in word_as_bytes {
+                        buffer[byte_offset] = byte;
+                        byte_offset += 1;
+                    }
+                }
+
+                if leftover_bytes != 0 {
+                    self.wait();
+
+                    let word_as_bytes = self.eeprom.eerdwr.read().bits().to_le_bytes();
+
+                    self.increment_offset(&mut address_copy)?;
+
+                    for index in 0..leftover_bytes {
+                        buffer[byte_offset] = word_as_bytes[index];
+                        byte_offset += 1;
+                    }
+                }
+
+                self.wait();
+
+                Ok(())
+            }
+        }
+
+        impl Eeprom {
+            /// Reads the word at `index`, counting from the start of the
+            /// EEPROM
+            fn read_word_at(&mut self, index: usize) -> Result<u32, EepromError> {
+                let address = self.word_index_to_address(index)?;
+                self.set_block_and_offset(&address)?;
+                Ok(self.eeprom.eerdwr.read().bits())
+            }
+
+            /// Writes the word at `index`, counting from the start of the
+            /// EEPROM
+            fn write_word_at(&mut self, index: usize, word: u32) -> Result<(), EepromError> {
+                let address = self.word_index_to_address(index)?;
+                self.set_block_and_offset(&address)?;
+                unsafe {
+                    self.eeprom.eerdwr.write(|w| w.bits(word));
+                }
+                self.write_status()
+            }
+
+            /// Reads words starting at `address`, which may run on into
+            /// the following blocks. Within each block the words are read
+            /// through EERDWRINC, which steps the offset in hardware.
+            pub fn read_words(
+                &mut self,
+                address: &EepromAddress,
+                words: &mut [u32],
+            ) -> Result<(), EepromError> {
+                let start = self.address_to_word_index(address)?;
+                if start
+                    .checked_add(words.len())
+                    .map_or(true, |end| end > EEPROM_END_ADDRESS_WORDS)
+                {
+                    return Err(EepromError::ReadWouldOverflow);
+                }
+                let mut index = start;
+                let mut words = words;
+                while !words.is_empty() {
+                    let address = self.word_index_to_address(index)?;
+                    let count = (EEPROM_BLOCK_SIZE - address.offset()).min(words.len());
+                    self.set_block_and_offset(&address)?;
+                    let (chunk, rest) = words.split_at_mut(count);
+                    for word in chunk.iter_mut() {
+                        *word = self.eeprom.eerdwrinc.read().bits();
+                    }
+                    index += count;
+                    words = rest;
+                }
+                Ok(())
+            }
+
+            /// Writes words starting at `address`, which may run on into
+            /// the following blocks. Within each block the words are
+            /// written through EERDWRINC, which steps the offset in
+            /// hardware.
+            pub fn write_words(
+                &mut self,
+                address: &EepromAddress,
+                words: &[u32],
+            ) -> Result<(), EepromError> {
+                let start = self.address_to_word_index(address)?;
+                if start
+                    .checked_add(words.len())
+                    .map_or(true, |end| end > EEPROM_END_ADDRESS_WORDS)
+                {
+                    return Err(EepromError::WriteWouldOverflow);
+                }
+                let mut index = start;
+                let mut words = words;
+                while !words.is_empty() {
+                    let address = self.word_index_to_address(index)?;
+                    let count = (EEPROM_BLOCK_SIZE - address.offset()).min(words.len());
+                    self.set_block_and_offset(&address)?;
+                    for word in &words[..count] {
+                        unsafe {
+                            self.eeprom.eerdwrinc.write(|w| w.bits(*word));
+                        }
+                        self.write_status()?;
+                    }
+                    index += count;
+                    words = &words[count..];
+                }
+                Ok(())
+            }
+
+            /// Erases the whole EEPROM through EEDBGME, setting every word
+            /// to 0xFFFF_FFFF and removing all passwords and protection.
+            /// This only works when the EEPROM is not locked by a master
+            /// password. The EEPROM is reset afterwards, as the datasheet
+            /// requires.
+            pub fn mass_erase(&mut self, pc: &sysctl::PowerControl) -> Result<(), EepromError> {
+                if self.is_busy() {
+                    return Err(EepromError::Busy);
+                }
+                unsafe {
+                    self.eeprom.eedbgme.write(|w| w.bits(0xE37B_0000 | 1));
+                }
+                while self.eeprom.eedbgme.read().bits() & 1 != 0 {}
+                self.recover(pc)
+            }
+        }
+
+        impl embedded_storage::ReadStorage for Eeprom {
+            type Error = EepromError;
+
+            /// Reads bytes from any byte offset in the EEPROM
+            fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), EepromError> {
+                let start = offset as usize;
+                if start
+                    .checked_add(bytes.len())
+                    .map_or(true, |end| end > EEPROM_END_ADDRESS_BYTES)
+                {
+                    return Err(EepromError::ReadWouldOverflow);
+                }
+                let mut position = start;
+                let mut bytes = bytes;
+                while !bytes.is_empty() {
+                    let first = position % BYTES_PER_WORD;
+                    let count = (BYTES_PER_WORD - first).min(bytes.len());
+                    let word = self.read_word_at(position / BYTES_PER_WORD)?.to_le_bytes();
+                    let (head, rest) = bytes.split_at_mut(count);
+                    head.copy_from_slice(&word[first..first + count]);
+                    position += count;
+                    bytes = rest;
+                }
+                Ok(())
+            }
+
+            fn capacity(&self) -> usize {
+                EEPROM_END_ADDRESS_BYTES
+            }
+        }
+
+        impl embedded_storage::Storage for Eeprom {
+            /// Writes bytes at any byte offset in the EEPROM. Words that are
+            /// only partly written are read, modified and written back.
+            fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), EepromError> {
+                let start = offset as usize;
+                if start
+                    .checked_add(bytes.len())
+                    .map_or(true, |end| end > EEPROM_END_ADDRESS_BYTES)
+                {
+                    return Err(EepromError::WriteWouldOverflow);
+                }
+                let mut position = start;
+                let mut bytes = bytes;
+                while !bytes.is_empty() {
+                    let index = position / BYTES_PER_WORD;
+                    let first = position % BYTES_PER_WORD;
+                    let count = (BYTES_PER_WORD - first).min(bytes.len());
+                    let mut word = if count == BYTES_PER_WORD {
+                        [0; BYTES_PER_WORD]
+                    } else {
+                        self.read_word_at(index)?.to_le_bytes()
+                    };
+                    word[first..first + count].copy_from_slice(&bytes[..count]);
+                    self.write_word_at(index, u32::from_le_bytes(word))?;
+                    position += count;
+                    bytes = &bytes[count..];
+                }
+                Ok(())
+            }
+        }
+
+        impl Erase for Eeprom {
+            fn erase(
+                &mut self,
+                address: &EepromAddress,
+                length_bytes: usize,
+            ) -> Result<(), EepromError> {
+                if self.is_busy() {
+                    return Err(EepromError::Busy);
+                }
+
+                if !self.is_access_valid(address, length_bytes) {
+                    return Err(EepromError::WriteWouldOverflow);
+                }
+
+                let num_words = length_bytes / BYTES_PER_WORD;
+                let leftover_bytes = length_bytes % BYTES_PER_WORD;
+                let mut address_copy = *address;
+
+                self.set_block_and_offset(&address)?;
+
+                let zero = 0 as u32;
+                for _i in 0..num_words {
+                    self.wait();
+
+                    unsafe {
+                        self.eeprom.eerdwr.write(|w| w.bits(zero));
+                    }
+
+                    self.write_status()?;
+
+                    self.increment_offset(&mut address_copy)?;
+                }
+
+                // Special case here, need to read-modify-write
+                if leftover_bytes != 0 {
+                    self.wait();
+
+                    let mut word = self.eeprom.eerdwr.read().bits().to_le_bytes();
+
+                    for i in 0..leftover_bytes {
+                        word[i] = 0;
+                    }
+
+                    unsafe {
+                        self.eeprom
+                            .eerdwr
+                            .write(|w| w.bits(u32::from_le_bytes(word)));
+                    }
+
+                    self.write_status()?;
+                }
+
+                self.wait();
+
+                Ok(())
+            }
+
+            fn erase_block(&mut self, block: usize) -> Result<(), EepromError> {
+                if self.is_busy() {
+                    return Err(EepromError::Busy);
+                }
+
+                self.set_block(block)?;
+
+                let mut address = EepromAddress::new(block, 0);
+
+                let zeros = [0 as u8; EEPROM_BLOCK_SIZE * BYTES_PER_WORD];
+
+                self.write(&mut address, &zeros)?;
+
+                self.wait();
+
+                Ok(())
+            }
+        }
+    };
+}
@@ -0,0 +1,457 @@
+//! Common flash memory controller code for TM4C123 and TM4C129
+
+use embedded_storage::nor_flash::{NorFlashError, NorFlashErrorKind};
+
+/// Number of words in the flash write buffer
+pub const WRITE_BUFFER_WORDS: usize = 32;
+
+/// Flash error
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The offset or length is not a multiple of the write or erase size
+    NotAligned,
+    /// The offset or length is beyond the end of the flash
+    OutOfBounds,
+    /// The flash is protected against the operation (FMPPE or FMPRE)
+    Protected,
+    /// The flash controller reported an error while programming or erasing
+    Program,
+}
+
+impl NorFlashError for Error {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Error::NotAligned => NorFlashErrorKind::NotAligned,
+            Error::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            _ => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// Checks an operation on `length` bytes at `offset` fits in `capacity`
+/// bytes and is aligned to `align` bytes, which must be a power of two
+pub fn check_range(offset: u32, length: usize, align: u32, capacity: usize) -> Result<(), Error> {
+    let end = (offset as usize)
+        .checked_add(length)
+        .ok_or(Error::OutOfBounds)?;
+    if end > capacity {
+        Err(Error::OutOfBounds)
+    } else if offset & (align - 1) != 0 || length & (align as usize - 1) != 0 {
+        Err(Error::NotAligned)
+    } else {
+        Ok(())
+    }
+}
+
+/// Works out the result of an operation from the flash controller raw
+/// interrupt status (FCRIS)
+pub fn check_status(fcris: u32) -> Result<(), Error> {
+    // ARIS
+    if fcris & (1 << 0) != 0 {
+        Err(Error::Protected)
+    // VOLTRIS, INVDRIS, ERRIS and PROGRIS
+    } else if fcris & (0b1011 << 10 | 1 << 9) != 0 {
+        Err(Error::Program)
+    } else {
+        Ok(())
+    }
+}
+
+/// How a protection block of flash may be accessed, set by the FMPRE and
+/// FMPPE registers
+///
+/// Protection can only be tightened: clearing an FMPRE or FMPPE bit is
+/// reversed by a reset if it is not committed, and only by the debug port
+/// unlock sequence (which mass-erases the flash) if it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protection {
+    /// The block can be read, executed, erased and programmed
+    None,
+    /// The block can be read and executed, but not erased or programmed
+    ReadOnly,
+    /// The block can't be read as data, but can still be executed, erased
+    /// and programmed. The datasheet notes this is unlikely to be useful;
+    /// use `ExecuteOnly` to lock down firmware.
+    NoReadProgrammable,
+    /// The block can only be executed: reading it as data fails, and it
+    /// cannot be erased or programmed (execute-only protection)
+    ExecuteOnly,
+}
+
+impl Protection {
+    /// Returns the protection given by an FMPRE and an FMPPE bit
+    pub fn from_bits(read_enable: bool, program_enable: bool) -> Protection {
+        match (read_enable, program_enable) {
+            (true, true) => Protection::None,
+            (true, false) => Protection::ReadOnly,
+            (false, true) => Protection::NoReadProgrammable,
+            (false, false) => Protection::ExecuteOnly,
+        }
+    }
+
+    /// Returns whether the FMPRE bit is set for this protection
+    pub fn read_enable(self) -> bool {
+        matches!(self, Protection::None | Protection::ReadOnly)
+    }
+
+    /// Returns whether the FMPPE bit is set for this protection
+    pub fn program_enable(self) -> bool {
+        matches!(self, Protection::None | Protection::NoReadProgrammable)
+    }
+}
+
+/// One of the non-volatile user registers
+///
+/// These are free for the application to use, although TM4C129 boards from
+/// TI store the Ethernet MAC address in `Reg0` and `Reg1`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UserRegister {
+    /// USER_REG0
+    Reg0 = 0,
+    /// USER_REG1
+    Reg1 = 1,
+    /// USER_REG2
+    Reg2 = 2,
+    /// USER_REG3
+    Reg3 = 3,
+}
+
+/// FMA value to commit USER_REG0. The others follow it.
+pub const USER_REG_COMMIT: u32 = 0x8000_0000;
+
+#[macro_export]
+/// Implements the flash driver and embedded-storage for a chip
+///
+/// The invoking module must provide `fn write_key(&FLASH) -> u32`, returning
+/// the key for the upper half of FMC and FMC2.
+///
+/// Each protection block is covered by `$protection_bits` consecutive bits
+/// of the FMPREn and FMPPEn registers.
+macro_rules! flash_hal {
+    ($FLASH:ident, $page_size:expr, $size:ident, $protection_block:expr, $protection_bits:expr) => {
+        /// The size of an erase page, in bytes
+        pub const PAGE_SIZE: usize = $page_size;
+
+        /// The size of a block covered by one protection setting, in bytes
+        pub const PROTECTION_BLOCK_SIZE: usize = $protection_block;
+
+        /// The flash memory, addressed from zero
+        pub struct Flash {
+            flash: $FLASH,
+        }
+
+        impl Flash {
+            /// Takes control of the flash memory controller
+            pub fn new(flash: $FLASH) -> Self {
+                Flash { flash }
+            }
+
+            /// Releases the flash memory controller
+            pub fn free(self) -> $FLASH {
+                self.flash
+            }
+
+            /// Returns the size of the flash, in bytes
+            pub fn size(&self) -> usize {
+                ((self.flash.$size.read().bits() & 0xFFFF) as usize + 1) * 2048
+            }
+
+            /// Clears the status of the last operation
+            fn clear_status(&mut self) {
+                self.flash.fcmisc.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+            }
+
+            /// Erases the page containing `address`
+            pub fn erase_page(&mut self, address: u32) -> Result<(), Error> {
+                // A length of one byte, so `address` itself must be in range
+                check_range(address, 1, 1, self.size())?;
+                let key = write_key(&self.flash);
+                self.clear_status();
+                self.flash.fma.write(|w| unsafe { w.bits(address & !($page_size - 1)) });
+                self.flash.fmc.write(|w| unsafe { w.bits(key | 1 << 1) });
+                while self.flash.fmc.read().bits() & (1 << 1) != 0 {}
+                check_status(self.flash.fcris.read().bits())
+            }
+
+            /// Programs one word. The word must be erased first, as
+            /// programming can only clear bits.
+            pub fn write_word(&mut self, address: u32, word: u32) -> Result<(), Error> {
+                check_range(address, 4, 4, self.size())?;
+                let key = write_key(&self.flash);
+                self.clear_status();
+                self.flash.fma.write(|w| unsafe { w.bits(address) });
+                self.flash.fmd.write(|w| unsafe { w.bits(word) });
+                self.flash.fmc.write(|w| unsafe { w.bits(key | 1 << 0) });
+                while self.flash.fmc.read().bits() & (1 << 0) != 0 {}
+                check_status(self.flash.fcris.read().bits())
+            }
+
+            /// Programs words through the 32-word write buffer, one
+            /// 128-byte block at a time. The words must be erased first.
+            pub fn write_words(&mut self, address: u32, words: &[u32]) -> Result<(), Error> {
+                check_range(address, words.len() * 4, 4, self.size())?;
+                let key = write_key(&self.flash);
+                // The write buffer registers are consecutive words
+                let fwb = &self.flash.fwbn as *const _ as *mut u32;
+                let mut address = address;
+                let mut words = words;
+                while !words.is_empty() {
+                    let block = address & !(WRITE_BUFFER_WORDS as u32 * 4 - 1);
+                    let first = ((address - block) / 4) as usize;
+                    let count = (WRITE_BUFFER_WORDS - first).min(words.len());
+                    self.clear_status();
+                    for (i, word) in words[..count].iter().enumerate() {
+                        unsafe { core::ptr::write_volatile(fwb.add(first + i), *word) };
+                    }
+                    self.flash.fma.write(|w| unsafe { w.bits(block) });
+                    self.flash.fmc2.write(|w| unsafe { w.bits(key | 1 << 0) });
+                    while self.flash.fmc2.read().bits() & (1 << 0) != 0 {}
+                    check_status(self.flash.fcris.read().bits())?;
+                    address += count as u32 * 4;
+                    words = &words[count..];
+                }
+                Ok(())
+            }
+
+            /// Copies bytes out of the flash. Blocks that can't be read as
+            /// data (FMPRE clear) give `Error::Protected`.
+            pub fn read_bytes(&self, address: u32, bytes: &mut [u8]) -> Result<(), Error> {
+                check_range(address, bytes.len(), 1, self.size())?;
+                // Reading a block whose FMPRE bit is clear would raise a
+                // bus fault
+                if !bytes.is_empty() {
+                    let first = address as usize / $protection_block;
+                    let last = (address as usize + bytes.len() - 1) / $protection_block;
+                    for block in first..=last {
+                        if !self.protection((block * $protection_block) as u32)?.read_enable() {
+                            return Err(Error::Protected);
+                        }
+                    }
+                }
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    let address = address as usize + i;
+                    *byte = if address == 0 {
+                        // Flash starts at address zero, which can't be read
+                        // through a Rust pointer
+                        let value: u32;
+                        unsafe {
+                            core::arch::asm!(
+                                "ldrb {0}, [{1}]",
+                                out(reg) value,
+                                in(reg) 0u32,
+                                options(nostack, readonly, preserves_flags),
+                            )
+                        };
+                        value as u8
+                    } else {
+                        unsafe { core::ptr::read_volatile(address as *const u8) }
+                    };
+                }
+                Ok(())
+            }
+
+            /// Returns the first FMPREn register, FMPPEn, and the mask of
+            /// the bits within them for the protection block at `address`
+            fn protection_bits(&self, address: u32) -> (*mut u32, *mut u32, usize, u32) {
+                let first = address as usize / $protection_block * $protection_bits;
+                let mask = ((1u64 << $protection_bits) - 1) << (first % 32);
+                (
+                    &self.flash.fmpre0 as *const _ as *mut u32,
+                    &self.flash.fmppe0 as *const _ as *mut u32,
+                    first / 32,
+                    mask as u32,
+                )
+            }
+
+            /// Returns the protection of the block containing `address`
+            pub fn protection(&self, address: u32) -> Result<Protection, Error> {
+                // A length of one byte, so `address` itself must be in range
+                check_range(address, 1, 1, self.size())?;
+                let (fmpre, fmppe, n, mask) = self.protection_bits(address);
+                let (read, program) = unsafe {
+                    (
+                        core::ptr::read_volatile(fmpre.add(n)) & mask == mask,
+                        core::ptr::read_volatile(fmppe.add(n)) & mask == mask,
+                    )
+                };
+                Ok(Protection::from_bits(read, program))
+            }
+
+            /// Protects the blocks from `from` to `to`, which must be
+            /// multiples of `PROTECTION_BLOCK_SIZE`. Protection that is
+            /// already tighter is left alone.
+            ///
+            /// The change takes effect immediately, but is lost at the next
+            /// reset unless it is committed.
+            pub fn protect(
+                &mut self,
+                from: u32,
+                to: u32,
+                protection: Protection,
+            ) -> Result<Uncommitted<'_>, Error> {
+                let pending = self.set_protection(from, to, protection)?;
+                Ok(Uncommitted { flash: self, pending })
+            }
+
+            fn set_protection(&mut self, from: u32, to: u32, protection: Protection) -> Result<u64, Error> {
+                if to < from {
+                    return Err(Error::OutOfBounds);
+                }
+                check_range(from, (to - from) as usize, $protection_block, self.size())?;
+                let mut pending = 0;
+                for block in (from..to).step_by($protection_block) {
+                    let (fmpre, fmppe, n, mask) = self.protection_bits(block);
+                    unsafe {
+                        if !protection.read_enable() {
+                            let value = core::ptr::read_volatile(fmpre.add(n));
+                            core::ptr::write_volatile(fmpre.add(n), value & !mask);
+                            pending |= 1 << (2 * n);
+                        }
+                        if !protection.program_enable() {
+                            let value = core::ptr::read_volatile(fmppe.add(n));
+                            core::ptr::write_volatile(fmppe.add(n), value & !mask);
+                            pending |= 1 << (2 * n + 1);
+                        }
+                    }
+                }
+                Ok(pending)
+            }
+
+            /// Returns the value of a user register
+            pub fn user_register(&self, register: UserRegister) -> u32 {
+                let userreg = &self.flash.userreg0 as *const _ as *const u32;
+                unsafe { core::ptr::read_volatile(userreg.add(register as usize)) }
+            }
+
+            /// Sets the value of a user register. Only bits that are set
+            /// can be changed, by clearing them.
+            ///
+            /// The change takes effect immediately, but is lost at the next
+            /// reset unless it is committed.
+            pub fn set_user_register(&mut self, register: UserRegister, value: u32) -> Uncommitted<'_> {
+                let pending = self.write_user_register(register, value);
+                Uncommitted { flash: self, pending }
+            }
+
+            fn write_user_register(&mut self, register: UserRegister, value: u32) -> u64 {
+                let userreg = &self.flash.userreg0 as *const _ as *mut u32;
+                unsafe { core::ptr::write_volatile(userreg.add(register as usize), value) };
+                1 << (32 + register as u32)
+            }
+
+            /// Commits the current value of the register at commit address
+            /// `fma`
+            fn commit_register(&mut self, fma: u32, value: u32) -> Result<(), Error> {
+                let key = write_key(&self.flash);
+                self.clear_status();
+                self.flash.fmd.write(|w| unsafe { w.bits(value) });
+                self.flash.fma.write(|w| unsafe { w.bits(fma) });
+                self.flash.fmc.write(|w| unsafe { w.bits(key | 1 << 3) });
+                while self.flash.fmc.read().bits() & (1 << 3) != 0 {}
+                check_status(self.flash.fcris.read().bits())
+            }
+        }
+
+        /// Protection and user register changes that are in effect, but will
+        /// be lost at the next reset unless they are committed
+        #[must_use = "the changes are lost at the next reset unless committed"]
+        pub struct Uncommitted<'a> {
+            flash: &'a mut Flash,
+            /// Bit `2n` is FMPREn, `2n + 1` is FMPPEn and `32 + n` is
+            /// USER_REGn
+            pending: u64,
+        }
+
+        impl<'a> Uncommitted<'a> {
+            /// Protects more blocks, as `Flash::protect`
+            pub fn protect(mut self, from: u32, to: u32, protection: Protection) -> Result<Self, Error> {
+                self.pending |= self.flash.set_protection(from, to, protection)?;
+                Ok(self)
+            }
+
+            /// Sets another user register, as `Flash::set_user_register`
+            pub fn set_user_register(mut self, register: UserRegister, value: u32) -> Self {
+                self.pending |= self.flash.write_user_register(register, value);
+                self
+            }
+
+            /// Leaves the changes in effect until the next reset, without
+            /// committing them
+            pub fn keep_volatile(self) {}
+
+            /// Writes the changes to non-volatile storage, so they persist
+            /// across resets. This is permanent: committed protection can
+            /// only be removed by the debug port unlock sequence, which
+            /// mass-erases the flash.
+            pub fn commit(self) -> Result<(), Error> {
+                for bit in 0..36 {
+                    if self.pending & (1 << bit) == 0 {
+                        continue;
+                    }
+                    let (fma, register) = if bit < 32 {
+                        let base = if bit % 2 == 0 {
+                            &self.flash.flash.fmpre0 as *const _ as *const u32
+                        } else {
+                            &self.flash.flash.fmppe0 as *const _ as *const u32
+                        };
+                        (bit, unsafe { base.add(bit as usize / 2) })
+                    } else {
+                        let base = &self.flash.flash.userreg0 as *const _ as *const u32;
+                        (USER_REG_COMMIT + bit - 32, unsafe { base.add(bit as usize - 32) })
+                    };
+                    let value = unsafe { core::ptr::read_volatile(register) };
+                    self.flash.commit_register(fma, value)?;
+                }
+                Ok(())
+            }
+        }
+
+        impl embedded_storage::nor_flash::ErrorType for Flash {
+            type Error = Error;
+        }
+
+        impl embedded_storage::nor_flash::ReadNorFlash for Flash {
+            const READ_SIZE: usize = 1;
+
+            fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+                self.read_bytes(offset, bytes)
+            }
+
+            fn capacity(&self) -> usize {
+                self.size()
+            }
+        }
+
+        impl embedded_storage::nor_flash::NorFlash for Flash {
+            const WRITE_SIZE: usize = 4;
+            const ERASE_SIZE: usize = $page_size;
+
+            fn erase(&mut self, from: u32, to: u32) -> Result<(), Error> {
+                if to < from {
+                    return Err(Error::OutOfBounds);
+                }
+                check_range(from, (to - from) as usize, $page_size, self.size())?;
+                for page in (from..to).step_by($page_size) {
+                    self.erase_page(page)?;
+                }
+                Ok(())
+            }
+
+            fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error> {
+                check_range(offset, bytes.len(), 4, self.size())?;
+                let mut words = [0u32; WRITE_BUFFER_WORDS];
+                let mut offset = offset;
+                for chunk in bytes.chunks(WRITE_BUFFER_WORDS * 4) {
+                    let count = chunk.len() / 4;
+                    for (word, bytes) in words.iter_mut().zip(chunk.chunks_exact(4)) {
+                        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                    }
+                    self.write_words(offset, &words[..count])?;
+                    offset += chunk.len() as u32;
+                }
+                Ok(())
+            }
+        }
+    };
+}
@@ -174,7 +174,15 @@ impl AlternateFunctionChoice for AF9 {
     }
 }
 
-// 10 through 13 are not available on this chip.
+// 10 through 12 are not available on this chip.
+
+/// Alternate function 13 (type state). Only used on the TM4C129.
+pub struct AF13;
+impl AlternateFunctionChoice for AF13 {
+    fn number() -> u32 {
+        13
+    }
+}
 
 /// Alternate function 14 (type state)
 pub struct AF14;
@@ -184,6 +192,14 @@ impl AlternateFunctionChoice for AF14 {
     }
 }
 
+/// Alternate function 15 (type state). Only used on the TM4C129.
+pub struct AF15;
+impl AlternateFunctionChoice for AF15 {
+    fn number() -> u32 {
+        15
+    }
+}
+
 /// Pin is locked through the GPIOCR register
 pub struct Locked;
 
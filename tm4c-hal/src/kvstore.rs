@@ -0,0 +1,665 @@
+//! A wear-levelled key/value store in the EEPROM
+//!
+//! The store uses a range of EEPROM blocks, split into two halves. Values are
+//! appended as records to the active half, so repeatedly setting a key
+//! writes each word of the half in turn instead of the same words every
+//! time. When the active half is full, the latest value of each key is
+//! copied to the other half, which then becomes active.
+//!
+//! Each record ends with a CRC, so a record torn by power loss is detected
+//! and ignored when the store is next mounted, leaving the previous value of
+//! that key. A half only becomes active once its header is written, after
+//! all the values have been copied into it, so power loss while compacting
+//! leaves the old half in use.
+
+use crate::crc::Crc32;
+use crate::eeprom::{Blocks, EepromAddress, EepromError, Erase, Read, Write};
+
+/// The largest value that can be stored, in bytes
+pub const MAX_VALUE_SIZE: usize = 64;
+
+/// Marks the first word of the active half. The low 16 bits are the
+/// generation, which increases each time the store is compacted.
+const HALF_MAGIC: u32 = 0x4B56_0000;
+
+/// Marks the first word of a record. Bits 16 to 23 are the value length and
+/// the low 16 bits are the key.
+const RECORD_MAGIC: u32 = 0xA500_0000;
+
+/// The value length recorded when a key is removed
+const TOMBSTONE: usize = 0xFF;
+
+/// Key/value store error
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The EEPROM driver returned an error
+    Eeprom(EepromError),
+    /// The blocks given for the store are not an even number of at least two
+    InvalidRegion,
+    /// The store is full, even after compaction
+    Full,
+    /// The value is larger than `MAX_VALUE_SIZE`
+    ValueTooLarge,
+    /// The buffer is too small for the stored value
+    BufferTooSmall,
+    /// The stored value is not the size of the requested type
+    WrongSize,
+}
+
+impl From<EepromError> for Error {
+    fn from(e: EepromError) -> Error {
+        Error::Eeprom(e)
+    }
+}
+
+/// A value with a fixed size in bytes, which can be kept in the store
+pub trait Value: Sized {
+    /// The size of the value, in bytes
+    const SIZE: usize;
+
+    /// Writes the value into `bytes`, which is `SIZE` bytes long
+    fn to_bytes(&self, bytes: &mut [u8]);
+
+    /// Reads a value from `bytes`, which is `SIZE` bytes long
+    fn from_bytes(bytes: &[u8]) -> Self;
+}
+
+macro_rules! value_impl {
+    ($($T:ty,)+) => {
+        $(
+            impl Value for $T {
+                const SIZE: usize = core::mem::size_of::<$T>();
+
+                fn to_bytes(&self, bytes: &mut [u8]) {
+                    bytes.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn from_bytes(bytes: &[u8]) -> Self {
+                    let mut array = [0; core::mem::size_of::<$T>()];
+                    array.copy_from_slice(bytes);
+                    <$T>::from_le_bytes(array)
+                }
+            }
+        )+
+    }
+}
+
+value_impl! {
+    u8, u16, u32, u64, i8, i16, i32, i64, f32, f64,
+}
+
+impl Value for bool {
+    const SIZE: usize = 1;
+
+    fn to_bytes(&self, bytes: &mut [u8]) {
+        bytes[0] = u8::from(*self);
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        bytes[0] != 0
+    }
+}
+
+impl<const N: usize> Value for [u8; N] {
+    const SIZE: usize = N;
+
+    fn to_bytes(&self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(self);
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut array = [0; N];
+        array.copy_from_slice(bytes);
+        array
+    }
+}
+
+/// A record found in the active half
+#[derive(Clone, Copy)]
+struct Record {
+    /// Word offset of the record in the half
+    position: usize,
+    key: u16,
+    /// Value length, or `TOMBSTONE`
+    length: usize,
+}
+
+impl Record {
+    /// Returns the number of words the record takes up
+    fn words(&self) -> usize {
+        2 + data_words(self.length)
+    }
+}
+
+/// Returns the number of data words for a value length
+fn data_words(length: usize) -> usize {
+    if length == TOMBSTONE {
+        0
+    } else {
+        length.div_ceil(4)
+    }
+}
+
+/// A key/value store in a range of EEPROM blocks
+pub struct KvStore<E> {
+    eeprom: E,
+    first_block: usize,
+    half_blocks: usize,
+    block_size: usize,
+    active: usize,
+    generation: u16,
+    /// Word offset in the active half of the end of the log
+    end: usize,
+}
+
+impl<E> KvStore<E>
+where
+    E: Blocks + Read + Write + Erase,
+{
+    /// Opens the store in `block_count` blocks starting at `first_block`,
+    /// recovering from any interrupted write. If the blocks do not hold a
+    /// store yet, they are formatted as an empty one.
+    pub fn mount(eeprom: E, first_block: usize, block_count: usize) -> Result<Self, Error> {
+        if block_count < 2 || block_count % 2 == 1 {
+            return Err(Error::InvalidRegion);
+        }
+        let block_size = eeprom.block_size()?;
+        let mut store = KvStore {
+            eeprom,
+            first_block,
+            half_blocks: block_count / 2,
+            block_size,
+            active: 0,
+            generation: 0,
+            end: 1,
+        };
+
+        let mut headers = [store.read_word(0, 0)?, store.read_word(1, 0)?];
+        let valid = |header: u32| header & 0xFFFF_0000 == HALF_MAGIC;
+        let generation = |header: u32| header as u16;
+        store.active = match (valid(headers[0]), valid(headers[1])) {
+            (true, true) => {
+                // The generation wraps, so compare the difference
+                if (generation(headers[1]).wrapping_sub(generation(headers[0])) as i16) > 0 {
+                    1
+                } else {
+                    0
+                }
+            }
+            (true, false) => 0,
+            (false, true) => 1,
+            (false, false) => {
+                store.erase_half(0)?;
+                store.write_words(0, 0, &[HALF_MAGIC])?;
+                headers[0] = HALF_MAGIC;
+                0
+            }
+        };
+        store.generation = generation(headers[store.active]);
+
+        // Find the end of the log, which is the first word that doesn't
+        // start a valid record
+        store.end = 1;
+        while let Some(record) = store.record_at(store.end)? {
+            store.end += record.words();
+        }
+        Ok(store)
+    }
+
+    /// Releases the EEPROM
+    pub fn free(self) -> E {
+        self.eeprom
+    }
+
+    /// Returns the number of words in each half
+    fn half_words(&self) -> usize {
+        self.half_blocks * self.block_size
+    }
+
+    /// Returns the EEPROM address of a word in a half
+    fn address(&self, half: usize, offset: usize) -> EepromAddress {
+        let word = (self.first_block + half * self.half_blocks) * self.block_size + offset;
+        EepromAddress::new(word / self.block_size, word % self.block_size)
+    }
+
+    fn read_word(&mut self, half: usize, offset: usize) -> Result<u32, Error> {
+        let mut bytes = [0u8; 4];
+        let address = self.address(half, offset);
+        self.eeprom.read(&address, 4, &mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn write_words(&mut self, half: usize, offset: usize, words: &[u32]) -> Result<(), Error> {
+        let mut bytes = [0u8; 4 * (2 + MAX_VALUE_SIZE / 4)];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(words.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        let address = self.address(half, offset);
+        self.eeprom.write(&address, &bytes[..words.len() * 4])?;
+        Ok(())
+    }
+
+    fn erase_half(&mut self, half: usize) -> Result<(), Error> {
+        let first = self.first_block + half * self.half_blocks;
+        for block in first..first + self.half_blocks {
+            self.eeprom.erase_block(block)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the value of a record into `buffer`, and returns the CRC of
+    /// the record's header and data
+    fn read_value(
+        &mut self,
+        half: usize,
+        record: &Record,
+        buffer: &mut [u8],
+    ) -> Result<u32, Error> {
+        let header = RECORD_MAGIC | (record.length as u32) << 16 | u32::from(record.key);
+        let mut crc = Crc32::new();
+        crc.update(&header.to_le_bytes());
+        for i in 0..data_words(record.length) {
+            let word = self.read_word(half, record.position + 1 + i)?.to_le_bytes();
+            crc.update(&word);
+            for (j, byte) in word.iter().enumerate() {
+                if let Some(b) = buffer.get_mut(i * 4 + j) {
+                    *b = *byte;
+                }
+            }
+        }
+        Ok(crc.finish())
+    }
+
+    /// Returns the record at `position` in the active half, if there is a
+    /// complete one with a correct CRC
+    fn record_at(&mut self, position: usize) -> Result<Option<Record>, Error> {
+        if position + 2 > self.half_words() {
+            return Ok(None);
+        }
+        let header = self.read_word(self.active, position)?;
+        let length = ((header >> 16) & 0xFF) as usize;
+        if header & 0xFF00_0000 != RECORD_MAGIC || (length > MAX_VALUE_SIZE && length != TOMBSTONE)
+        {
+            return Ok(None);
+        }
+        let record = Record {
+            position,
+            key: header as u16,
+            length,
+        };
+        if position + record.words() > self.half_words() {
+            return Ok(None);
+        }
+        let crc = self.read_value(self.active, &record, &mut [])?;
+        let stored = self.read_word(self.active, position + record.words() - 1)?;
+        Ok(if crc == stored { Some(record) } else { None })
+    }
+
+    /// Returns the latest record for `key` at or after `from`, if any
+    fn find(&mut self, key: u16, from: usize) -> Result<Option<Record>, Error> {
+        let mut found = None;
+        let mut position = from;
+        while position < self.end {
+            match self.record_at(position)? {
+                Some(record) => {
+                    if record.key == key {
+                        found = Some(record);
+                    }
+                    position += record.words();
+                }
+                None => break,
+            }
+        }
+        Ok(found)
+    }
+
+    /// Appends a record to `half` at `position`, and returns its length in
+    /// words
+    fn append(
+        &mut self,
+        half: usize,
+        position: usize,
+        key: u16,
+        length: usize,
+        value: &[u8],
+    ) -> Result<usize, Error> {
+        let header = RECORD_MAGIC | (length as u32) << 16 | u32::from(key);
+        let mut words = [0u32; 2 + MAX_VALUE_SIZE / 4];
+        let count = data_words(length);
+        let mut crc = Crc32::new();
+        words[0] = header;
+        crc.update(&header.to_le_bytes());
+        for i in 0..count {
+            let mut bytes = [0u8; 4];
+            for (j, byte) in bytes.iter_mut().enumerate() {
+                *byte = value.get(i * 4 + j).copied().unwrap_or(0);
+            }
+            crc.update(&bytes);
+            words[1 + i] = u32::from_le_bytes(bytes);
+        }
+        words[1 + count] = crc.finish();
+        let total = count + 2;
+        self.write_words(half, position, &words[..total])?;
+
+        // Left over words from a torn write may follow, so make sure the
+        // log ends here
+        let next = position + total;
+        if next < self.half_words() && self.read_word(half, next)? != 0 {
+            self.write_words(half, next, &[0])?;
+        }
+        Ok(total)
+    }
+
+    /// Copies the latest value of every key to the other half, and makes it
+    /// the active half
+    fn compact(&mut self) -> Result<(), Error> {
+        let target = 1 - self.active;
+        self.erase_half(target)?;
+        let mut end = 1;
+        let mut position = 1;
+        while position < self.end {
+            let record = match self.record_at(position)? {
+                Some(record) => record,
+                None => break,
+            };
+            position += record.words();
+            let latest = self.find(record.key, position)?.is_none();
+            if latest && record.length != TOMBSTONE {
+                let mut value = [0u8; MAX_VALUE_SIZE];
+                self.read_value(self.active, &record, &mut value)?;
+                end += self.append(target, end, record.key, record.length, &value)?;
+            }
+        }
+        let generation = self.generation.wrapping_add(1);
+        self.write_words(target, 0, &[HALF_MAGIC | u32::from(generation)])?;
+        self.active = target;
+        self.generation = generation;
+        self.end = end;
+        Ok(())
+    }
+
+    /// Appends a record for `key`, compacting the store first if needed
+    fn put(&mut self, key: u16, length: usize, value: &[u8]) -> Result<(), Error> {
+        let words = 2 + data_words(length);
+        if self.end + words > self.half_words() {
+            self.compact()?;
+            if self.end + words > self.half_words() {
+                return Err(Error::Full);
+            }
+        }
+        self.end += self.append(self.active, self.end, key, length, value)?;
+        Ok(())
+    }
+
+    /// Reads the value of `key` into `buffer`, returning its length, or
+    /// `None` if the key is not set
+    pub fn get_bytes(&mut self, key: u16, buffer: &mut [u8]) -> Result<Option<usize>, Error> {
+        match self.find(key, 1)? {
+            Some(record) if record.length != TOMBSTONE => {
+                if buffer.len() < record.length {
+                    return Err(Error::BufferTooSmall);
+                }
+                self.read_value(self.active, &record, buffer)?;
+                Ok(Some(record.length))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Sets the value of `key`
+    pub fn set_bytes(&mut self, key: u16, value: &[u8]) -> Result<(), Error> {
+        if value.len() > MAX_VALUE_SIZE {
+            return Err(Error::ValueTooLarge);
+        }
+        self.put(key, value.len(), value)
+    }
+
+    /// Returns the value of `key`, or `None` if the key is not set
+    pub fn get<V>(&mut self, key: u16) -> Result<Option<V>, Error>
+    where
+        V: Value,
+    {
+        let mut buffer = [0u8; MAX_VALUE_SIZE];
+        match self.get_bytes(key, &mut buffer)? {
+            Some(length) if length == V::SIZE => Ok(Some(V::from_bytes(&buffer[..length]))),
+            Some(_) => Err(Error::WrongSize),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the value of `key`
+    pub fn set<V>(&mut self, key: u16, value: &V) -> Result<(), Error>
+    where
+        V: Value,
+    {
+        if V::SIZE > MAX_VALUE_SIZE {
+            return Err(Error::ValueTooLarge);
+        }
+        let mut buffer = [0u8; MAX_VALUE_SIZE];
+        value.to_bytes(&mut buffer[..V::SIZE]);
+        self.set_bytes(key, &buffer[..V::SIZE])
+    }
+
+    /// Removes `key` from the store
+    pub fn remove(&mut self, key: u16) -> Result<(), Error> {
+        match self.find(key, 1)? {
+            Some(record) if record.length != TOMBSTONE => self.put(key, TOMBSTONE, &[]),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eeprom::Blocks;
+
+    const BLOCK_SIZE: usize = 16;
+
+    /// EEPROM held in memory, blank (all ones) to start with
+    struct MockEeprom {
+        words: [u32; 4 * BLOCK_SIZE],
+        /// Power is lost when a write reaches this word index: it and the
+        /// rest of the write are not written
+        power_loss_at: Option<usize>,
+    }
+
+    impl MockEeprom {
+        fn new() -> Self {
+            MockEeprom {
+                words: [0xFFFF_FFFF; 4 * BLOCK_SIZE],
+                power_loss_at: None,
+            }
+        }
+
+        fn index(address: &EepromAddress) -> usize {
+            address.block() * BLOCK_SIZE + address.offset()
+        }
+    }
+
+    impl Blocks for MockEeprom {
+        fn block_size(&self) -> Result<usize, EepromError> {
+            Ok(BLOCK_SIZE)
+        }
+
+        fn word_index_to_address(&self, index: usize) -> Result<EepromAddress, EepromError> {
+            Ok(EepromAddress::new(index / BLOCK_SIZE, index % BLOCK_SIZE))
+        }
+
+        fn address_to_word_index(&self, address: &EepromAddress) -> Result<usize, EepromError> {
+            Ok(Self::index(address))
+        }
+    }
+
+    impl Read for MockEeprom {
+        fn read(
+            &mut self,
+            address: &EepromAddress,
+            bytes_to_read: usize,
+            buffer: &mut [u8],
+        ) -> Result<(), EepromError> {
+            let start = Self::index(address);
+            for (i, byte) in buffer[..bytes_to_read].iter_mut().enumerate() {
+                *byte = self.words[start + i / 4].to_le_bytes()[i % 4];
+            }
+            Ok(())
+        }
+    }
+
+    impl Write for MockEeprom {
+        fn write(&mut self, address: &EepromAddress, data: &[u8]) -> Result<(), EepromError> {
+            let start = Self::index(address);
+            for (i, chunk) in data.chunks(4).enumerate() {
+                if self.power_loss_at == Some(start + i) {
+                    return Err(EepromError::ProgramRetry);
+                }
+                let mut bytes = self.words[start + i].to_le_bytes();
+                bytes[..chunk.len()].copy_from_slice(chunk);
+                self.words[start + i] = u32::from_le_bytes(bytes);
+            }
+            Ok(())
+        }
+    }
+
+    impl Erase for MockEeprom {
+        fn erase(
+            &mut self,
+            address: &EepromAddress,
+            length_bytes: usize,
+        ) -> Result<(), EepromError> {
+            let start = Self::index(address);
+            for word in &mut self.words[start..start + length_bytes.div_ceil(4)] {
+                *word = 0;
+            }
+            Ok(())
+        }
+
+        fn erase_block(&mut self, block: usize) -> Result<(), EepromError> {
+            self.erase(&EepromAddress::new(block, 0), BLOCK_SIZE * 4)
+        }
+    }
+
+    #[test]
+    fn blank_mount_formats() {
+        let mut store = KvStore::mount(MockEeprom::new(), 0, 4).unwrap();
+        assert_eq!(store.generation, 0);
+        assert_eq!(store.get::<u32>(1).unwrap(), None);
+    }
+
+    #[test]
+    fn survives_compaction_and_remount() {
+        let mut store = KvStore::mount(MockEeprom::new(), 0, 4).unwrap();
+        store.set(2, &0xAAAA_u16).unwrap();
+        // Each record is three words and a half holds 31 after its header,
+        // so this compacts exactly once
+        for value in 0..12u32 {
+            store.set(1, &value).unwrap();
+        }
+        assert_eq!(store.active, 1);
+        assert_eq!(store.generation, 1);
+
+        let mut store = KvStore::mount(store.free(), 0, 4).unwrap();
+        assert_eq!(store.active, 1);
+        assert_eq!(store.get::<u32>(1).unwrap(), Some(11));
+        assert_eq!(store.get::<u16>(2).unwrap(), Some(0xAAAA));
+    }
+
+    #[test]
+    fn removed_key_stays_removed() {
+        let mut store = KvStore::mount(MockEeprom::new(), 0, 4).unwrap();
+        store.set(1, &5u8).unwrap();
+        store.remove(1).unwrap();
+        for value in 0..40u32 {
+            store.set(2, &value).unwrap();
+        }
+
+        let mut store = KvStore::mount(store.free(), 0, 4).unwrap();
+        assert_eq!(store.get::<u8>(1).unwrap(), None);
+        assert_eq!(store.get::<u32>(2).unwrap(), Some(39));
+    }
+
+    #[test]
+    fn torn_record_is_ignored() {
+        let mut store = KvStore::mount(MockEeprom::new(), 0, 4).unwrap();
+        store.set(1, &10u32).unwrap();
+        // The next record is at word 4, so its CRC is word 6
+        store.eeprom.power_loss_at = Some(6);
+        assert_eq!(
+            store.set(1, &20u32),
+            Err(Error::Eeprom(EepromError::ProgramRetry))
+        );
+
+        let mut eeprom = store.free();
+        eeprom.power_loss_at = None;
+        let mut store = KvStore::mount(eeprom, 0, 4).unwrap();
+        assert_eq!(store.end, 4);
+        assert_eq!(store.get::<u32>(1).unwrap(), Some(10));
+        store.set(1, &30u32).unwrap();
+        assert_eq!(store.get::<u32>(1).unwrap(), Some(30));
+    }
+
+    #[test]
+    fn interrupted_compaction_keeps_old_half() {
+        let mut store = KvStore::mount(MockEeprom::new(), 0, 4).unwrap();
+        store.set(2, &0xAAAA_u16).unwrap();
+        for value in 0..9u32 {
+            store.set(1, &value).unwrap();
+        }
+        // The half holds 31 words after its header, and ten records of three
+        // words fill it, so the next set compacts into the second half,
+        // whose header is word 32
+        store.eeprom.power_loss_at = Some(2 * BLOCK_SIZE);
+        assert_eq!(
+            store.set(1, &10u32),
+            Err(Error::Eeprom(EepromError::ProgramRetry))
+        );
+
+        let mut eeprom = store.free();
+        eeprom.power_loss_at = None;
+        // The values were copied, but the header was not written
+        assert_eq!(eeprom.words[2 * BLOCK_SIZE], 0);
+        assert_ne!(eeprom.words[2 * BLOCK_SIZE + 1], 0);
+        let mut store = KvStore::mount(eeprom, 0, 4).unwrap();
+        assert_eq!(store.active, 0);
+        assert_eq!(store.generation, 0);
+        assert_eq!(store.get::<u32>(1).unwrap(), Some(8));
+        assert_eq!(store.get::<u16>(2).unwrap(), Some(0xAAAA));
+    }
+
+    #[test]
+    fn words_after_torn_record_are_cut_off() {
+        // A five word value whose last three words look like a valid
+        // record for key 2
+        let mut hidden = [0u8; 12];
+        let header = RECORD_MAGIC | 4 << 16 | 2;
+        let mut crc = Crc32::new();
+        crc.update(&header.to_le_bytes());
+        crc.update(&99u32.to_le_bytes());
+        hidden[..4].copy_from_slice(&header.to_le_bytes());
+        hidden[4..8].copy_from_slice(&99u32.to_le_bytes());
+        hidden[8..].copy_from_slice(&crc.finish().to_le_bytes());
+        let mut value = [0u8; 20];
+        value[8..].copy_from_slice(&hidden);
+
+        let mut store = KvStore::mount(MockEeprom::new(), 0, 4).unwrap();
+        store.set(1, &10u32).unwrap();
+        // The long record is at word 4, with its data in words 5 to 9 and
+        // its CRC in word 10
+        store.eeprom.power_loss_at = Some(10);
+        assert!(store.set_bytes(3, &value).is_err());
+
+        let mut eeprom = store.free();
+        eeprom.power_loss_at = None;
+        let mut store = KvStore::mount(eeprom, 0, 4).unwrap();
+        assert_eq!(store.end, 4);
+        // This record ends at word 7, where the hidden record starts
+        store.set(1, &20u32).unwrap();
+        assert_eq!(store.eeprom.words[7], 0);
+
+        let mut store = KvStore::mount(store.free(), 0, 4).unwrap();
+        assert_eq!(store.end, 7);
+        assert_eq!(store.get::<u32>(1).unwrap(), Some(20));
+        assert_eq!(store.get::<u32>(2).unwrap(), None);
+        assert_eq!(store.get_bytes(3, &mut [0; MAX_VALUE_SIZE]).unwrap(), None);
+    }
+}
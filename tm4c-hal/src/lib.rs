@@ -1,17 +1,27 @@
 //! Generic implementation code for both TM4C123 and TM4C129.
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![deny(missing_docs, warnings)]
 #![allow(deprecated)]
 
 pub mod bb;
+pub mod boot;
+pub mod comp;
+pub mod crc;
 pub mod delay;
 pub mod eeprom;
+pub mod flash;
 pub mod gpio;
 pub mod i2c;
+pub mod kvstore;
+pub mod mcpwm;
+pub mod qei;
+pub mod rom;
 pub mod serial;
+pub mod spi;
 pub mod sysctl;
 pub mod time;
+pub mod watchdog;
 
 ///! An internal macro to implement the GPIO functionality for each port
 #[macro_export]
@@ -365,6 +375,20 @@ macro_rules! gpio_macro {
 
                 }
 
+                impl<AF, MODE> $PXi<AlternateFunction<AF, MODE>>
+                where
+                    AF: AlternateFunctionChoice,
+                    MODE: OutputMode,
+                {
+                    /// Connects the pin to its alternate function, or
+                    /// disconnects it so that it becomes an input and stops
+                    /// driving the line. Any pull-up or pull-down is kept.
+                    pub fn set_connected(&mut self, connected: bool) {
+                        let p = unsafe { &*$GPIOX::ptr() };
+                        unsafe { bb::change_bit(&p.afsel, $i, connected); }
+                    }
+                }
+
                 impl<MODE> $PXi<MODE> {
                     /// Erases the pin number from the type
                     ///
@@ -0,0 +1,1101 @@
+//! Common code for the Motion Control PWM modules (M0PWM and M1PWM).
+//!
+//! Each PWM module has four generators. Each generator has one 16-bit
+//! counter, two comparators and two outputs (A and B), so a module drives up
+//! to eight pins (MnPWM0 through MnPWM7).
+
+/// Divides the system clock down to produce the PWM clock
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockDivider {
+    /// PWM clock is the system clock
+    _1,
+    /// PWM clock is the system clock divided by 2
+    _2,
+    /// PWM clock is the system clock divided by 4
+    _4,
+    /// PWM clock is the system clock divided by 8
+    _8,
+    /// PWM clock is the system clock divided by 16
+    _16,
+    /// PWM clock is the system clock divided by 32
+    _32,
+    /// PWM clock is the system clock divided by 64
+    _64,
+}
+
+impl ClockDivider {
+    /// Returns the value to divide the system clock by
+    pub fn divisor(self) -> u32 {
+        match self {
+            ClockDivider::_1 => 1,
+            ClockDivider::_2 => 2,
+            ClockDivider::_4 => 4,
+            ClockDivider::_8 => 8,
+            ClockDivider::_16 => 16,
+            ClockDivider::_32 => 32,
+            ClockDivider::_64 => 64,
+        }
+    }
+
+    /// Returns the value for the PWMDIV field, or `None` if the divider is to
+    /// be bypassed.
+    pub fn pwmdiv(self) -> Option<u8> {
+        match self {
+            ClockDivider::_1 => None,
+            ClockDivider::_2 => Some(0),
+            ClockDivider::_4 => Some(1),
+            ClockDivider::_8 => Some(2),
+            ClockDivider::_16 => Some(3),
+            ClockDivider::_32 => Some(4),
+            ClockDivider::_64 => Some(5),
+        }
+    }
+}
+
+/// How a generator's counter counts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CountMode {
+    /// Count down from the load value to zero, then reload. Produces
+    /// left-aligned pulses.
+    Down,
+    /// Count up from zero to the load value, then back down to zero.
+    /// Produces centre-aligned pulses.
+    UpDown,
+}
+
+/// What a generator does to an output when a counter event occurs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// Leave the output alone
+    Nothing = 0,
+    /// Invert the output
+    Invert = 1,
+    /// Drive the output low
+    Low = 2,
+    /// Drive the output high
+    High = 3,
+}
+
+impl Action {
+    fn inverted(self) -> Action {
+        match self {
+            Action::Low => Action::High,
+            Action::High => Action::Low,
+            a => a,
+        }
+    }
+}
+
+/// The actions a generator takes on one of its outputs for each counter
+/// event. This is the PWMnGENA / PWMnGENB register.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Actions {
+    /// Counter reaches zero
+    pub zero: Action,
+    /// Counter reaches the load value
+    pub load: Action,
+    /// Counter matches comparator A while counting up
+    pub cmpa_up: Action,
+    /// Counter matches comparator A while counting down
+    pub cmpa_down: Action,
+    /// Counter matches comparator B while counting up
+    pub cmpb_up: Action,
+    /// Counter matches comparator B while counting down
+    pub cmpb_down: Action,
+}
+
+impl Default for Actions {
+    fn default() -> Actions {
+        Actions::constant(Action::Nothing)
+    }
+}
+
+impl Actions {
+    /// Performs the same action on every event
+    pub fn constant(action: Action) -> Actions {
+        Actions {
+            zero: action,
+            load: action,
+            cmpa_up: action,
+            cmpa_down: action,
+            cmpb_up: action,
+            cmpb_down: action,
+        }
+    }
+
+    /// Swaps every drive-high action for drive-low and vice versa
+    pub fn inverted(self) -> Actions {
+        Actions {
+            zero: self.zero.inverted(),
+            load: self.load.inverted(),
+            cmpa_up: self.cmpa_up.inverted(),
+            cmpa_down: self.cmpa_down.inverted(),
+            cmpb_up: self.cmpb_up.inverted(),
+            cmpb_down: self.cmpb_down.inverted(),
+        }
+    }
+
+    /// Encodes the actions in the PWMnGENA / PWMnGENB register layout
+    pub fn bits(self) -> u32 {
+        (self.zero as u32)
+            | (self.load as u32) << 2
+            | (self.cmpa_up as u32) << 4
+            | (self.cmpa_down as u32) << 6
+            | (self.cmpb_up as u32) << 8
+            | (self.cmpb_down as u32) << 10
+    }
+
+    /// Decodes the actions from the PWMnGENA / PWMnGENB register layout
+    pub fn from_bits(bits: u32) -> Actions {
+        let action = |shift: u32| match (bits >> shift) & 0b11 {
+            0 => Action::Nothing,
+            1 => Action::Invert,
+            2 => Action::Low,
+            _ => Action::High,
+        };
+        Actions {
+            zero: action(0),
+            load: action(2),
+            cmpa_up: action(4),
+            cmpa_down: action(6),
+            cmpb_up: action(8),
+            cmpb_down: action(10),
+        }
+    }
+}
+
+/// Selects which of a generator's two comparators sets the duty cycle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparator {
+    /// Comparator A
+    A,
+    /// Comparator B
+    B,
+}
+
+/// When writes to a generator's registers take effect
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpdateMode {
+    /// Straight away. Only the dead-band registers support this. The load
+    /// and comparator registers behave as for `Local`, and so do the action
+    /// registers, so that they always change in step with the comparators.
+    Immediate,
+    /// The next time the counter reaches zero
+    Local,
+    /// The next time the counter reaches zero after the update has been
+    /// requested with `Control::update`, so that several generators can be
+    /// changed on the same period boundary.
+    Global,
+}
+
+impl UpdateMode {
+    /// Encodes the mode for a two-bit update field (PWMnCTL GENxUPD and
+    /// DBxUPD, and PWMENUPD)
+    pub fn bits(self) -> u32 {
+        match self {
+            UpdateMode::Immediate => 0,
+            UpdateMode::Local => 2,
+            UpdateMode::Global => 3,
+        }
+    }
+}
+
+/// A generator counter event, which can raise an interrupt or trigger the
+/// ADC
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// Counter reaches zero
+    Zero,
+    /// Counter reaches the load value
+    Load,
+    /// Counter matches comparator A while counting up
+    CmpAUp,
+    /// Counter matches comparator A while counting down
+    CmpADown,
+    /// Counter matches comparator B while counting up
+    CmpBUp,
+    /// Counter matches comparator B while counting down
+    CmpBDown,
+}
+
+impl Event {
+    /// Returns the bit for this event in the PWMnINTEN (interrupt half),
+    /// PWMnRIS and PWMnISC registers
+    pub fn mask(self) -> u32 {
+        1 << (self as u32)
+    }
+}
+
+/// One of a PWM module's fault inputs (MnFAULTx)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultInput {
+    /// MnFAULT0
+    Fault0,
+    /// MnFAULT1
+    Fault1,
+    /// MnFAULT2
+    Fault2,
+    /// MnFAULT3
+    Fault3,
+}
+
+impl FaultInput {
+    /// Returns the bit for this input in the fault registers
+    pub fn mask(self) -> u32 {
+        1 << (self as u32)
+    }
+}
+
+/// The level on a fault input which signals a fault
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultPolarity {
+    /// A high level is a fault
+    ActiveHigh,
+    /// A low level is a fault
+    ActiveLow,
+}
+
+/// Generator 0 (outputs MnPWM0 and MnPWM1) (type state)
+pub struct G0;
+/// Generator 1 (outputs MnPWM2 and MnPWM3) (type state)
+pub struct G1;
+/// Generator 2 (outputs MnPWM4 and MnPWM5) (type state)
+pub struct G2;
+/// Generator 3 (outputs MnPWM6 and MnPWM7) (type state)
+pub struct G3;
+
+/// Output A of a generator (the even numbered MnPWMx pin) (type state)
+pub struct A;
+/// Output B of a generator (the odd numbered MnPWMx pin) (type state)
+pub struct B;
+
+/// Returns the maximum duty cycle for a generator with the given count mode
+/// and load value.
+pub fn max_duty(mode: CountMode, load: u16) -> u16 {
+    match mode {
+        CountMode::Down => load.saturating_add(1),
+        CountMode::UpDown => load,
+    }
+}
+
+/// Returns the load value which gives a period of `ticks` PWM clocks
+pub fn load_for_period(mode: CountMode, ticks: u32) -> u16 {
+    let load = match mode {
+        CountMode::Down => ticks.saturating_sub(1),
+        CountMode::UpDown => ticks / 2,
+    };
+    if load > u32::from(u16::MAX) {
+        u16::MAX
+    } else {
+        load as u16
+    }
+}
+
+/// Works out the generator actions and comparator value which give the
+/// requested duty cycle (in PWM clock ticks, up to `max_duty`).
+///
+/// In `Down` mode the output goes high on load and low on the compare
+/// match, giving a left-aligned pulse. In `UpDown` mode the output goes high
+/// on the compare match when counting up and low again when counting down,
+/// giving a pulse centred on the load value. Zero and 100% duty are handled
+/// by driving the output constantly, as the hardware ignores a compare match
+/// which coincides with the zero or load events.
+pub fn duty_actions(
+    mode: CountMode,
+    load: u16,
+    duty: u16,
+    comparator: Comparator,
+    invert: bool,
+) -> (Actions, u16) {
+    let (actions, compare) = if duty == 0 {
+        (Actions::constant(Action::Low), 0)
+    } else if duty >= max_duty(mode, load) {
+        (Actions::constant(Action::High), 0)
+    } else {
+        let compare = load - duty;
+        let mut actions = Actions::default();
+        let (up, down) = match mode {
+            CountMode::Down => {
+                actions.zero = Action::Low;
+                actions.load = Action::High;
+                (Action::Nothing, Action::Low)
+            }
+            CountMode::UpDown => {
+                actions.zero = Action::Low;
+                (Action::High, Action::Low)
+            }
+        };
+        match comparator {
+            Comparator::A => {
+                actions.cmpa_up = up;
+                actions.cmpa_down = down;
+            }
+            Comparator::B => {
+                actions.cmpb_up = up;
+                actions.cmpb_down = down;
+            }
+        }
+        (actions, compare)
+    };
+    if invert {
+        (actions.inverted(), compare)
+    } else {
+        (actions, compare)
+    }
+}
+
+/// Works out the duty cycle (in PWM clock ticks) from the generator actions
+/// and comparator value. This is the inverse of `duty_actions`.
+pub fn actions_duty(
+    mode: CountMode,
+    load: u16,
+    actions: Actions,
+    compare: u16,
+    invert: bool,
+) -> u16 {
+    let actions = if invert { actions.inverted() } else { actions };
+    if actions == Actions::constant(Action::Low) {
+        0
+    } else if actions == Actions::constant(Action::High) {
+        max_duty(mode, load)
+    } else {
+        load.saturating_sub(compare)
+    }
+}
+
+#[macro_export]
+/// Implements the PWM module driver for a chip.
+///
+/// The invoking module must provide the pin traits `PinA<PWM, GEN>`,
+/// `PinB<PWM, GEN>` and `FaultPin<PWM>`, and a
+/// `set_clock_divider(&PWM, ClockDivider)` function. The fault sense register
+/// is optional, as not every generator has one.
+macro_rules! mcpwm_hal {
+    ($($PWMn:ident: ($powerDomain:ident, [
+        $($G:ident: ($ctl:ident, $load:ident, $count:ident, $cmpa:ident, $cmpb:ident,
+            $gena:ident, $genb:ident, $dbctl:ident, $dbrise:ident, $dbfall:ident,
+            $fltsrc0:ident, $minfltper:ident, $fltstat0:ident, $inten:ident, $ris:ident, $isc:ident
+            $(, $fltsen:ident)?; $i:expr),)+
+    ]),)+) => {
+        /// Extension trait to split a PWM module into its generators
+        pub trait PwmExt {
+            /// The parts to split the PWM module into
+            type Parts;
+
+            /// Powers up the PWM module, sets the PWM clock divider and
+            /// splits the module into its generators.
+            fn split(
+                self,
+                divider: ClockDivider,
+                clocks: &Clocks,
+                pc: &sysctl::PowerControl,
+            ) -> Self::Parts;
+        }
+
+        /// PWM module parts
+        pub struct Parts<PWM> {
+            /// Module-wide controls
+            pub control: Control<PWM>,
+            /// Generator 0 (MnPWM0 and MnPWM1)
+            pub gen0: Generator<PWM, G0>,
+            /// Generator 1 (MnPWM2 and MnPWM3)
+            pub gen1: Generator<PWM, G1>,
+            /// Generator 2 (MnPWM4 and MnPWM5)
+            pub gen2: Generator<PWM, G2>,
+            /// Generator 3 (MnPWM6 and MnPWM7)
+            pub gen3: Generator<PWM, G3>,
+        }
+
+        /// Controls which affect the whole PWM module
+        pub struct Control<PWM> {
+            pwm: PWM,
+            clock: Hertz,
+        }
+
+        /// One of the four generators in a PWM module
+        pub struct Generator<PWM, GEN> {
+            clock: Hertz,
+            _pwm: PhantomData<PWM>,
+            _gen: PhantomData<GEN>,
+        }
+
+        /// One output of a generator, with its duty cycle set by the
+        /// comparator of the same name.
+        pub struct Channel<PWM, GEN, OUT, PIN> {
+            pin: PIN,
+            _pwm: PhantomData<PWM>,
+            _gen: PhantomData<GEN>,
+            _out: PhantomData<OUT>,
+        }
+
+        /// Both outputs of a generator, driven from comparator A so that
+        /// output B is always the inverse of output A.
+        pub struct Complementary<PWM, GEN, PINA, PINB> {
+            pins: (PINA, PINB),
+            _pwm: PhantomData<PWM>,
+            _gen: PhantomData<GEN>,
+        }
+
+        impl<PWM, GEN, OUT, PIN> Channel<PWM, GEN, OUT, PIN> {
+            /// Releases the pin
+            pub fn free(self) -> PIN {
+                self.pin
+            }
+        }
+
+        impl<PWM, GEN, PINA, PINB> Complementary<PWM, GEN, PINA, PINB> {
+            /// Releases the pins
+            pub fn free(self) -> (PINA, PINB) {
+                self.pins
+            }
+        }
+
+        impl<PWM> Control<PWM> {
+            /// Returns the frequency of the PWM clock
+            pub fn clock(&self) -> Hertz {
+                self.clock
+            }
+        }
+
+        $(
+            impl PwmExt for $PWMn {
+                type Parts = Parts<$PWMn>;
+
+                fn split(
+                    self,
+                    divider: ClockDivider,
+                    clocks: &Clocks,
+                    pc: &sysctl::PowerControl,
+                ) -> Parts<$PWMn> {
+                    sysctl::control_power(
+                        pc, sysctl::Domain::$powerDomain,
+                        sysctl::RunMode::Run, sysctl::PowerState::On);
+                    sysctl::reset(pc, sysctl::Domain::$powerDomain);
+
+                    set_clock_divider(&self, divider);
+                    let clock = Hertz(clocks.sysclk.0 / divider.divisor());
+
+                    Parts {
+                        control: Control { pwm: self, clock },
+                        gen0: Generator { clock, _pwm: PhantomData, _gen: PhantomData },
+                        gen1: Generator { clock, _pwm: PhantomData, _gen: PhantomData },
+                        gen2: Generator { clock, _pwm: PhantomData, _gen: PhantomData },
+                        gen3: Generator { clock, _pwm: PhantomData, _gen: PhantomData },
+                    }
+                }
+            }
+
+            impl Control<$PWMn> {
+                /// Releases the PWM module. The generators should be
+                /// dropped first.
+                pub fn free(self) -> $PWMn {
+                    self.pwm
+                }
+
+                /// Sets which outputs are enabled. Bit n is MnPWMn. A disabled
+                /// output is held low (or high, if it is inverted).
+                pub fn set_output_enable(&mut self, mask: u8) {
+                    self.pwm.enable.write(|w| unsafe { w.bits(mask.into()) });
+                }
+
+                /// Returns which outputs are enabled. Bit n is MnPWMn.
+                pub fn output_enable(&self) -> u8 {
+                    self.pwm.enable.read().bits() as u8
+                }
+
+                /// Sets which outputs are inverted. Bit n is MnPWMn.
+                pub fn set_output_invert(&mut self, mask: u8) {
+                    self.pwm.invert.write(|w| unsafe { w.bits(mask.into()) });
+                }
+
+                /// Returns which outputs are inverted. Bit n is MnPWMn.
+                pub fn output_invert(&self) -> u8 {
+                    self.pwm.invert.read().bits() as u8
+                }
+
+                /// Sets how the outputs behave while their generator is in
+                /// fault. Each output whose bit is set in `mask` is forced to
+                /// the level of its bit in `levels`, by the hardware, as soon as
+                /// the fault occurs. Outputs not in `mask` keep running. Bit n
+                /// is MnPWMn.
+                pub fn set_fault_outputs(&mut self, mask: u8, levels: u8) {
+                    self.pwm.faultval.write(|w| unsafe { w.bits(levels.into()) });
+                    self.pwm.fault.write(|w| unsafe { w.bits(mask.into()) });
+                }
+
+                /// Returns which generators are currently in fault. Bit n is
+                /// generator n.
+                pub fn faults(&self) -> u8 {
+                    (self.pwm.status.read().bits() & 0xF) as u8
+                }
+
+                /// Requests a globally synchronised update on the
+                /// generators in `mask` (bit n is generator n). Registers
+                /// written in `UpdateMode::Global` on those generators all
+                /// take effect the next time each counter reaches zero.
+                pub fn update(&mut self, mask: u8) {
+                    self.pwm.ctl.write(|w| unsafe { w.bits(u32::from(mask) & 0xF) });
+                }
+
+                /// Returns the generators with a requested global update
+                /// which has not yet happened. Bit n is generator n.
+                pub fn update_pending(&self) -> u8 {
+                    (self.pwm.ctl.read().bits() & 0xF) as u8
+                }
+
+                /// Resets the counters of the generators in `mask` (bit n is
+                /// generator n) at the same time, so their periods line up.
+                pub fn reset_counters(&mut self, mask: u8) {
+                    self.pwm.sync.write(|w| unsafe { w.bits(u32::from(mask) & 0xF) });
+                }
+
+                /// Sets when changes to the output enables (including those
+                /// made by the channels) take effect
+                pub fn set_enable_update_mode(&mut self, mode: UpdateMode) {
+                    let bits = (0..8).fold(0, |acc, n| acc | mode.bits() << (2 * n));
+                    self.pwm.enupd.write(|w| unsafe { w.bits(bits) });
+                }
+
+                /// Enables the interrupt for a fault input
+                pub fn listen_fault(&mut self, input: FaultInput) {
+                    cortex_m::interrupt::free(|_| {
+                        self.pwm.inten.modify(|r, w| unsafe { w.bits(r.bits() | input.mask() << 16) });
+                    });
+                }
+
+                /// Disables the interrupt for a fault input
+                pub fn unlisten_fault(&mut self, input: FaultInput) {
+                    cortex_m::interrupt::free(|_| {
+                        self.pwm.inten.modify(|r, w| unsafe { w.bits(r.bits() & !(input.mask() << 16)) });
+                    });
+                }
+
+                /// Returns whether the interrupt for a fault input is pending
+                pub fn fault_interrupt(&self, input: FaultInput) -> bool {
+                    self.pwm.isc.read().bits() & input.mask() << 16 != 0
+                }
+
+                /// Clears the pending interrupt for a fault input
+                pub fn clear_fault_interrupt(&mut self, input: FaultInput) {
+                    self.pwm.isc.write(|w| unsafe { w.bits(input.mask() << 16) });
+                }
+            }
+
+            $(
+                impl Generator<$PWMn, $G> {
+                    /// Sets the count mode and period (in PWM clock ticks),
+                    /// and starts the counter. The outputs stay off until a
+                    /// channel is enabled. The other settings in PWMnCTL,
+                    /// such as the update modes, are kept.
+                    pub fn configure(&mut self, mode: CountMode, period: u32) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        // Stop the counter while the mode changes
+                        unsafe { bb::change_bit(&p.$ctl, 0, false) };
+                        unsafe { bb::change_bit(&p.$ctl, 1, mode == CountMode::UpDown) };
+                        p.$load.write(|w| unsafe { w.bits(load_for_period(mode, period).into()) });
+                        p.$cmpa.write(|w| unsafe { w.bits(0) });
+                        p.$cmpb.write(|w| unsafe { w.bits(0) });
+                        p.$gena.write(|w| unsafe { w.bits(Actions::constant(Action::Low).bits()) });
+                        p.$genb.write(|w| unsafe { w.bits(Actions::constant(Action::Low).bits()) });
+                        // From now on the actions change at the same time
+                        // as the comparators (GENAUPD and GENBUPD). Setting
+                        // the Local bit leaves Global as it is.
+                        let sync = UpdateMode::Local.bits() << 6 | UpdateMode::Local.bits() << 8;
+                        p.$ctl.modify(|r, w| unsafe { w.bits(r.bits() | sync | 1) });
+                    }
+
+                    /// Reads the count mode and load value
+                    fn counter() -> (CountMode, u16) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        let mode = if bb::read_bit(&p.$ctl, 1) {
+                            CountMode::UpDown
+                        } else {
+                            CountMode::Down
+                        };
+                        (mode, p.$load.read().bits() as u16)
+                    }
+
+                    /// Returns the count mode
+                    pub fn mode(&self) -> CountMode {
+                        Self::counter().0
+                    }
+
+                    /// Sets the period, in PWM clock ticks. The duty cycles
+                    /// of the channels are not scaled to match.
+                    pub fn set_period(&mut self, period: u32) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        let load = load_for_period(self.mode(), period);
+                        p.$load.write(|w| unsafe { w.bits(load.into()) });
+                    }
+
+                    /// Returns the period, in PWM clock ticks
+                    pub fn period(&self) -> u32 {
+                        let (mode, load) = Self::counter();
+                        match mode {
+                            CountMode::Down => u32::from(load) + 1,
+                            CountMode::UpDown => u32::from(load) * 2,
+                        }
+                    }
+
+                    /// Returns the frequency of the PWM clock driving this
+                    /// generator
+                    pub fn clock(&self) -> Hertz {
+                        self.clock
+                    }
+
+                    /// Returns the current value of the counter
+                    pub fn count(&self) -> u16 {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$count.read().bits() as u16
+                    }
+
+                    /// Sets whether the counter keeps running while the
+                    /// processor is halted by the debugger
+                    pub fn run_on_debug(&mut self, run: bool) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        unsafe { bb::change_bit(&p.$ctl, 2, run) };
+                    }
+
+                    /// Starts the counter
+                    pub fn enable(&mut self) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        unsafe { bb::change_bit(&p.$ctl, 0, true) };
+                    }
+
+                    /// Stops the counter
+                    pub fn disable(&mut self) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        unsafe { bb::change_bit(&p.$ctl, 0, false) };
+                    }
+
+                    /// Sets the raw actions for output A
+                    pub fn set_actions_a(&mut self, actions: Actions) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$gena.write(|w| unsafe { w.bits(actions.bits()) });
+                    }
+
+                    /// Sets the raw actions for output B
+                    pub fn set_actions_b(&mut self, actions: Actions) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$genb.write(|w| unsafe { w.bits(actions.bits()) });
+                    }
+
+                    /// Sets the raw value of comparator A
+                    pub fn set_compare_a(&mut self, value: u16) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$cmpa.write(|w| unsafe { w.bits(value.into()) });
+                    }
+
+                    /// Sets the raw value of comparator B
+                    pub fn set_compare_b(&mut self, value: u16) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$cmpb.write(|w| unsafe { w.bits(value.into()) });
+                    }
+
+                    /// Sets when writes to this generator's load, comparator,
+                    /// action and dead-band registers take effect
+                    pub fn set_update_mode(&mut self, mode: UpdateMode) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        let global = u32::from(mode == UpdateMode::Global);
+                        // The comparators can't be updated immediately, so
+                        // the actions aren't either, or a duty change could
+                        // glitch
+                        let actions = if mode == UpdateMode::Immediate {
+                            UpdateMode::Local
+                        } else {
+                            mode
+                        };
+                        let fields = global << 3
+                            | global << 4
+                            | global << 5
+                            | actions.bits() << 6
+                            | actions.bits() << 8
+                            | mode.bits() << 10
+                            | mode.bits() << 12
+                            | mode.bits() << 14;
+                        p.$ctl.modify(|r, w| unsafe { w.bits(r.bits() & !0xFFF8 | fields) });
+                    }
+
+                    /// Starts listening for an event. Also enables this
+                    /// generator's interrupt at the module level.
+                    pub fn listen(&mut self, event: Event) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$inten.modify(|r, w| unsafe { w.bits(r.bits() | event.mask()) });
+                        cortex_m::interrupt::free(|_| {
+                            p.inten.modify(|r, w| unsafe { w.bits(r.bits() | 1 << $i) });
+                        });
+                    }
+
+                    /// Stops listening for an event. Once no events are
+                    /// left, this generator's interrupt is disabled at the
+                    /// module level.
+                    pub fn unlisten(&mut self, event: Event) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$inten.modify(|r, w| unsafe { w.bits(r.bits() & !event.mask()) });
+                        if p.$inten.read().bits() & 0x3F == 0 {
+                            cortex_m::interrupt::free(|_| {
+                                p.inten.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << $i)) });
+                            });
+                        }
+                    }
+
+                    /// Returns whether an event has occurred since its
+                    /// interrupt was last cleared, whether or not it is
+                    /// being listened for
+                    pub fn is_pending(&self, event: Event) -> bool {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$ris.read().bits() & event.mask() != 0
+                    }
+
+                    /// Clears the interrupt for an event
+                    pub fn clear_interrupt(&mut self, event: Event) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$isc.write(|w| unsafe { w.bits(event.mask()) });
+                    }
+
+                    /// Makes an event trigger the ADC. The ADC sample
+                    /// sequencer must also be set to trigger from this
+                    /// generator.
+                    pub fn enable_adc_trigger(&mut self, event: Event) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$inten.modify(|r, w| unsafe { w.bits(r.bits() | event.mask() << 8) });
+                    }
+
+                    /// Stops an event triggering the ADC
+                    pub fn disable_adc_trigger(&mut self, event: Event) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$inten.modify(|r, w| unsafe { w.bits(r.bits() & !(event.mask() << 8)) });
+                    }
+
+                    /// Enables the dead-band generator, with the given delays
+                    /// (in PWM clock ticks, up to 4095) on the rising and
+                    /// falling edges. Output A becomes the generated
+                    /// signal with its rising edge delayed, and output B
+                    /// becomes its inverse with the rising edge delayed by
+                    /// `fall`, so only the `complementary` channel makes
+                    /// sense while it is enabled.
+                    pub fn enable_dead_band(&mut self, rise: u16, fall: u16) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$dbrise.write(|w| unsafe { w.bits(rise.min(0xFFF).into()) });
+                        p.$dbfall.write(|w| unsafe { w.bits(fall.min(0xFFF).into()) });
+                        unsafe { bb::change_bit(&p.$dbctl, 0, true) };
+                    }
+
+                    /// Disables the dead-band generator
+                    pub fn disable_dead_band(&mut self) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        unsafe { bb::change_bit(&p.$dbctl, 0, false) };
+                    }
+
+                    /// Adds a fault pin to the inputs which put this
+                    /// generator in fault. While in fault, the outputs
+                    /// chosen with `Control::set_fault_outputs` are forced
+                    /// to their fault levels.
+                    pub fn add_fault_source<PIN>(&mut self, pin: &PIN)
+                    where
+                        PIN: FaultPin<$PWMn>,
+                    {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        let mask = pin.input().mask();
+                        p.$fltsrc0.modify(|r, w| unsafe { w.bits(r.bits() | mask) });
+                        unsafe { bb::change_bit(&p.$ctl, 16, true) };
+                    }
+
+                    /// Removes all the fault inputs from this generator
+                    pub fn clear_fault_sources(&mut self) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$fltsrc0.write(|w| unsafe { w.bits(0) });
+                        unsafe { bb::change_bit(&p.$ctl, 16, true) };
+                    }
+
+                    /// Sets whether a fault is latched. A latched fault
+                    /// holds the generator in fault until it is cleared
+                    /// with `clear_faults`, even once the input goes away.
+                    pub fn latch_faults(&mut self, latch: bool) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        unsafe { bb::change_bit(&p.$ctl, 18, latch) };
+                    }
+
+                    /// Sets the minimum time (in PWM clock ticks) the
+                    /// generator stays in fault once one occurs, or `None`
+                    /// to follow the fault inputs.
+                    pub fn set_min_fault_period(&mut self, period: Option<u16>) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        if let Some(period) = period {
+                            p.$minfltper.write(|w| unsafe { w.bits(period.into()) });
+                        }
+                        unsafe { bb::change_bit(&p.$ctl, 17, period.is_some()) };
+                    }
+
+                    /// Returns whether the given fault input is signalling
+                    /// (or has latched) a fault on this generator
+                    pub fn is_faulted(&self, input: FaultInput) -> bool {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$fltstat0.read().bits() & input.mask() != 0
+                    }
+
+                    /// Clears any latched faults
+                    pub fn clear_faults(&mut self) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        p.$fltstat0.write(|w| unsafe { w.bits(0xF) });
+                    }
+
+                    $(
+                        /// Sets the level on a fault input which this
+                        /// generator treats as a fault
+                        pub fn set_fault_polarity(&mut self, input: FaultInput, polarity: FaultPolarity) {
+                            let p = unsafe { &*$PWMn::ptr() };
+                            unsafe {
+                                bb::change_bit(
+                                    &p.$fltsen,
+                                    input as u8,
+                                    polarity == FaultPolarity::ActiveLow,
+                                )
+                            };
+                        }
+                    )?
+
+                    /// Uses output A as an independent PWM channel
+                    pub fn channel_a<PIN>(&self, pin: PIN) -> Channel<$PWMn, $G, A, PIN>
+                    where
+                        PIN: PinA<$PWMn, $G>,
+                    {
+                        Channel { pin, _pwm: PhantomData, _gen: PhantomData, _out: PhantomData }
+                    }
+
+                    /// Uses output B as an independent PWM channel
+                    pub fn channel_b<PIN>(&self, pin: PIN) -> Channel<$PWMn, $G, B, PIN>
+                    where
+                        PIN: PinB<$PWMn, $G>,
+                    {
+                        Channel { pin, _pwm: PhantomData, _gen: PhantomData, _out: PhantomData }
+                    }
+
+                    /// Uses both outputs as a complementary pair, where B is
+                    /// always the inverse of A.
+                    pub fn complementary<PINA, PINB>(
+                        &self,
+                        pin_a: PINA,
+                        pin_b: PINB,
+                    ) -> Complementary<$PWMn, $G, PINA, PINB>
+                    where
+                        PINA: PinA<$PWMn, $G>,
+                        PINB: PinB<$PWMn, $G>,
+                    {
+                        Complementary { pins: (pin_a, pin_b), _pwm: PhantomData, _gen: PhantomData }
+                    }
+                }
+
+                impl<PIN> PwmPin for Channel<$PWMn, $G, A, PIN> {
+                    type Duty = u16;
+
+                    fn disable(&mut self) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        unsafe { bb::change_bit(&p.enable, 2 * $i, false) };
+                    }
+
+                    fn enable(&mut self) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        unsafe { bb::change_bit(&p.enable, 2 * $i, true) };
+                    }
+
+                    fn get_duty(&self) -> u16 {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        let (mode, load) = Generator::<$PWMn, $G>::counter();
+                        let actions = Actions::from_bits(p.$gena.read().bits());
+                        actions_duty(mode, load, actions, p.$cmpa.read().bits() as u16, false)
+                    }
+
+                    fn get_max_duty(&self) -> u16 {
+                        let (mode, load) = Generator::<$PWMn, $G>::counter();
+                        max_duty(mode, load)
+                    }
+
+                    fn set_duty(&mut self, duty: u16) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        let (mode, load) = Generator::<$PWMn, $G>::counter();
+                        let (actions, compare) = duty_actions(mode, load, duty, Comparator::A, false);
+                        p.$cmpa.write(|w| unsafe { w.bits(compare.into()) });
+                        // Only the comparator changes, unless the duty moves to or
+                        // from 0 or 100%
+                        if p.$gena.read().bits() != actions.bits() {
+                            p.$gena.write(|w| unsafe { w.bits(actions.bits()) });
+                        }
+                    }
+                }
+
+                impl<PIN> PwmPin for Channel<$PWMn, $G, B, PIN> {
+                    type Duty = u16;
+
+                    fn disable(&mut self) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        unsafe { bb::change_bit(&p.enable, 2 * $i + 1, false) };
+                    }
+
+                    fn enable(&mut self) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        unsafe { bb::change_bit(&p.enable, 2 * $i + 1, true) };
+                    }
+
+                    fn get_duty(&self) -> u16 {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        let (mode, load) = Generator::<$PWMn, $G>::counter();
+                        let actions = Actions::from_bits(p.$genb.read().bits());
+                        actions_duty(mode, load, actions, p.$cmpb.read().bits() as u16, false)
+                    }
+
+                    fn get_max_duty(&self) -> u16 {
+                        let (mode, load) = Generator::<$PWMn, $G>::counter();
+                        max_duty(mode, load)
+                    }
+
+                    fn set_duty(&mut self, duty: u16) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        let (mode, load) = Generator::<$PWMn, $G>::counter();
+                        let (actions, compare) = duty_actions(mode, load, duty, Comparator::B, false);
+                        p.$cmpb.write(|w| unsafe { w.bits(compare.into()) });
+                        // Only the comparator changes, unless the duty moves to or
+                        // from 0 or 100%
+                        if p.$genb.read().bits() != actions.bits() {
+                            p.$genb.write(|w| unsafe { w.bits(actions.bits()) });
+                        }
+                    }
+                }
+
+                impl<PINA, PINB> PwmPin for Complementary<$PWMn, $G, PINA, PINB> {
+                    type Duty = u16;
+
+                    fn disable(&mut self) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        unsafe { bb::change_bit(&p.enable, 2 * $i, false) };
+                        unsafe { bb::change_bit(&p.enable, 2 * $i + 1, false) };
+                    }
+
+                    fn enable(&mut self) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        unsafe { bb::change_bit(&p.enable, 2 * $i, true) };
+                        unsafe { bb::change_bit(&p.enable, 2 * $i + 1, true) };
+                    }
+
+                    fn get_duty(&self) -> u16 {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        let (mode, load) = Generator::<$PWMn, $G>::counter();
+                        let actions = Actions::from_bits(p.$gena.read().bits());
+                        actions_duty(mode, load, actions, p.$cmpa.read().bits() as u16, false)
+                    }
+
+                    fn get_max_duty(&self) -> u16 {
+                        let (mode, load) = Generator::<$PWMn, $G>::counter();
+                        max_duty(mode, load)
+                    }
+
+                    fn set_duty(&mut self, duty: u16) {
+                        let p = unsafe { &*$PWMn::ptr() };
+                        let (mode, load) = Generator::<$PWMn, $G>::counter();
+                        let (actions, compare) = duty_actions(mode, load, duty, Comparator::A, false);
+                        let (inverse, _) = duty_actions(mode, load, duty, Comparator::A, true);
+                        p.$cmpa.write(|w| unsafe { w.bits(compare.into()) });
+                        // Only the comparator changes, unless the duty moves to or
+                        // from 0 or 100%
+                        if p.$gena.read().bits() != actions.bits() {
+                            p.$gena.write(|w| unsafe { w.bits(actions.bits()) });
+                        }
+                        if p.$genb.read().bits() != inverse.bits() {
+                            p.$genb.write(|w| unsafe { w.bits(inverse.bits()) });
+                        }
+                    }
+                }
+            )+
+        )+
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_down() {
+        assert_eq!(load_for_period(CountMode::Down, 100), 99);
+        assert_eq!(load_for_period(CountMode::Down, 0), 0);
+        assert_eq!(load_for_period(CountMode::Down, 65_536), u16::MAX);
+        assert_eq!(load_for_period(CountMode::Down, 1_000_000), u16::MAX);
+    }
+
+    #[test]
+    fn load_up_down() {
+        assert_eq!(load_for_period(CountMode::UpDown, 100), 50);
+        assert_eq!(load_for_period(CountMode::UpDown, 131_070), u16::MAX);
+        assert_eq!(load_for_period(CountMode::UpDown, 1_000_000), u16::MAX);
+    }
+
+    #[test]
+    fn left_aligned() {
+        let (actions, compare) = duty_actions(CountMode::Down, 99, 25, Comparator::A, false);
+        assert_eq!(compare, 74);
+        assert_eq!(
+            actions,
+            Actions {
+                zero: Action::Low,
+                load: Action::High,
+                cmpa_down: Action::Low,
+                ..Actions::default()
+            }
+        );
+    }
+
+    #[test]
+    fn centre_aligned() {
+        let (actions, compare) = duty_actions(CountMode::UpDown, 50, 20, Comparator::B, false);
+        assert_eq!(compare, 30);
+        assert_eq!(
+            actions,
+            Actions {
+                zero: Action::Low,
+                cmpb_up: Action::High,
+                cmpb_down: Action::Low,
+                ..Actions::default()
+            }
+        );
+    }
+
+    #[test]
+    fn off_and_on() {
+        for &mode in &[CountMode::Down, CountMode::UpDown] {
+            let max = max_duty(mode, 99);
+            let off = duty_actions(mode, 99, 0, Comparator::A, false).0;
+            assert_eq!(off, Actions::constant(Action::Low));
+            for &duty in &[max, max + 1, u16::MAX] {
+                let on = duty_actions(mode, 99, duty, Comparator::A, false).0;
+                assert_eq!(on, Actions::constant(Action::High));
+            }
+        }
+    }
+
+    #[test]
+    fn inverted() {
+        let (actions, compare) = duty_actions(CountMode::Down, 99, 25, Comparator::A, false);
+        let (inverse, inverse_compare) = duty_actions(CountMode::Down, 99, 25, Comparator::A, true);
+        assert_eq!(inverse, actions.inverted());
+        assert_eq!(inverse_compare, compare);
+        assert_eq!(
+            duty_actions(CountMode::Down, 99, 0, Comparator::A, true).0,
+            Actions::constant(Action::High)
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for &mode in &[CountMode::Down, CountMode::UpDown] {
+            for &invert in &[false, true] {
+                let load = 40;
+                for duty in 0..=max_duty(mode, load) {
+                    let (actions, compare) = duty_actions(mode, load, duty, Comparator::A, invert);
+                    let bits = Actions::from_bits(actions.bits());
+                    assert_eq!(bits, actions);
+                    assert_eq!(actions_duty(mode, load, bits, compare, invert), duty);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Common Quadrature Encoder Interface (QEI) code for TM4C123 and TM4C129
+
+/// How the PhA and PhB inputs are interpreted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignalMode {
+    /// PhA and PhB are quadrature encoded
+    Quadrature,
+    /// PhA is a clock and PhB is the direction (high for reverse)
+    ClockDirection,
+}
+
+/// Which edges are counted, in `SignalMode::Quadrature`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaptureMode {
+    /// Count both edges of PhA only, giving twice the line count per
+    /// revolution
+    PhA,
+    /// Count both edges of PhA and PhB, giving four times the line count per
+    /// revolution
+    PhAAndPhB,
+}
+
+/// What resets the position counter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResetMode {
+    /// The position wraps to zero after the maximum position (and back to the
+    /// maximum position below zero)
+    MaxPosition,
+    /// The position resets to zero on each index pulse
+    Index,
+}
+
+/// Divides the counted edges before they reach the velocity accumulator
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VelocityDivider {
+    /// Count every edge
+    _1 = 0,
+    /// Count every 2nd edge
+    _2 = 1,
+    /// Count every 4th edge
+    _4 = 2,
+    /// Count every 8th edge
+    _8 = 3,
+    /// Count every 16th edge
+    _16 = 4,
+    /// Count every 32nd edge
+    _32 = 5,
+    /// Count every 64th edge
+    _64 = 6,
+    /// Count every 128th edge
+    _128 = 7,
+}
+
+/// QEI configuration
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Config {
+    /// How the inputs are interpreted
+    pub signal_mode: SignalMode,
+    /// Which edges are counted
+    pub capture_mode: CaptureMode,
+    /// What resets the position counter
+    pub reset_mode: ResetMode,
+    /// Swap the PhA and PhB inputs, which reverses the direction
+    pub swap: bool,
+    /// The largest value of the position counter
+    pub max_position: u32,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            signal_mode: SignalMode::Quadrature,
+            capture_mode: CaptureMode::PhAAndPhB,
+            reset_mode: ResetMode::MaxPosition,
+            swap: false,
+            max_position: u32::MAX,
+        }
+    }
+}
+
+/// Interrupt events
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// An index pulse was detected
+    Index,
+    /// The velocity timer expired, so a new velocity is available
+    VelocityTimer,
+    /// The direction of rotation changed
+    Direction,
+    /// PhA and PhB changed at the same time, so counts may have been lost
+    PhaseError,
+}
+
+impl Event {
+    /// Returns the bit for this event in the interrupt registers
+    pub fn mask(self) -> u32 {
+        1 << (self as u32)
+    }
+}
+
+#[macro_export]
+/// Implements the pin traits for a QEI peripheral
+macro_rules! qei_pins {
+    ($QEIn:ident,
+        pha: [$(($($phagpio: ident)::*, $phaaf: ident)),*],
+        phb: [$(($($phbgpio: ident)::*, $phbaf: ident)),*],
+        idx: [$(($($idxgpio: ident)::*, $idxaf: ident)),*],
+    ) => {
+        $(
+            impl<T> PhAPin<$QEIn> for $($phagpio)::*<AlternateFunction<$phaaf, T>>
+            where
+                T: OutputMode,
+            {}
+        )*
+
+        $(
+            impl<T> PhBPin<$QEIn> for $($phbgpio)::*<AlternateFunction<$phbaf, T>>
+            where
+                T: OutputMode,
+            {}
+        )*
+
+        $(
+            impl<T> IdxPin<$QEIn> for $($idxgpio)::*<AlternateFunction<$idxaf, T>>
+            where
+                T: OutputMode,
+            {}
+        )*
+    }
+}
+
+#[macro_export]
+/// Implements the QEI driver and embedded-hal for a TM4C QEI peripheral
+macro_rules! qei_hal {
+    ($($QEIX:ident: ($powerDomain:ident, $qeiX:ident),)+) => {
+        $(
+            impl<PHA, PHB, IDX> Qei<$QEIX, (PHA, PHB, IDX)> {
+                /// Configures the QEI peripheral and starts counting. Pass
+                /// `()` as the index pin if there isn't one.
+                pub fn $qeiX(
+                    qei: $QEIX,
+                    pins: (PHA, PHB, IDX),
+                    config: Config,
+                    pc: &sysctl::PowerControl,
+                ) -> Self where
+                    PHA: PhAPin<$QEIX>,
+                    PHB: PhBPin<$QEIX>,
+                    IDX: IdxPin<$QEIX>,
+                {
+                    sysctl::control_power(
+                        pc, sysctl::Domain::$powerDomain,
+                        sysctl::RunMode::Run, sysctl::PowerState::On);
+                    sysctl::reset(pc, sysctl::Domain::$powerDomain);
+
+                    qei.maxpos.write(|w| unsafe { w.bits(config.max_position) });
+                    qei.pos.write(|w| unsafe { w.bits(0) });
+                    qei.ctl.write(|w| {
+                        w.swap().bit(config.swap);
+                        w.sigmode().bit(config.signal_mode == SignalMode::ClockDirection);
+                        w.capmode().bit(config.capture_mode == CaptureMode::PhAAndPhB);
+                        w.resmode().bit(config.reset_mode == ResetMode::Index);
+                        w.enable().set_bit()
+                    });
+
+                    Qei { qei, pins }
+                }
+
+                /// Releases the QEI peripheral and associated pins
+                pub fn free(self) -> ($QEIX, (PHA, PHB, IDX)) {
+                    (self.qei, self.pins)
+                }
+
+                /// Returns the position counter
+                pub fn position(&self) -> u32 {
+                    self.qei.pos.read().bits()
+                }
+
+                /// Sets the position counter
+                pub fn set_position(&mut self, position: u32) {
+                    self.qei.pos.write(|w| unsafe { w.bits(position) });
+                }
+
+                /// Returns the largest value of the position counter
+                pub fn max_position(&self) -> u32 {
+                    self.qei.maxpos.read().bits()
+                }
+
+                /// Sets the largest value of the position counter
+                pub fn set_max_position(&mut self, max_position: u32) {
+                    self.qei.maxpos.write(|w| unsafe { w.bits(max_position) });
+                }
+
+                /// Returns the direction of rotation
+                pub fn direction(&self) -> Direction {
+                    if self.qei.stat.read().direction().bit_is_set() {
+                        Direction::Downcounting
+                    } else {
+                        Direction::Upcounting
+                    }
+                }
+
+                /// Returns whether a phase error has been detected
+                pub fn has_error(&self) -> bool {
+                    self.qei.stat.read().error().bit_is_set()
+                }
+
+                /// Starts capturing the velocity. Edges are counted over
+                /// `period` system clock cycles, after `divider`, and the
+                /// count is then latched for `velocity`.
+                pub fn enable_velocity(&mut self, period: u32, divider: VelocityDivider) {
+                    self.qei.ctl.modify(|_, w| w.velen().clear_bit());
+                    self.qei.load.write(|w| unsafe { w.bits(period.saturating_sub(1)) });
+                    self.qei.ctl.modify(|_, w| unsafe {
+                        w.veldiv().bits(divider as u8);
+                        w.velen().set_bit()
+                    });
+                }
+
+                /// Stops capturing the velocity
+                pub fn disable_velocity(&mut self) {
+                    self.qei.ctl.modify(|_, w| w.velen().clear_bit());
+                }
+
+                /// Returns the number of edges counted in the last complete
+                /// velocity period
+                pub fn velocity(&self) -> u32 {
+                    self.qei.speed.read().bits()
+                }
+
+                /// Sets whether the QEI stops while the processor is halted by
+                /// the debugger
+                pub fn stall_on_debug(&mut self, stall: bool) {
+                    self.qei.ctl.modify(|_, w| w.stallen().bit(stall));
+                }
+
+                /// Starts listening for an interrupt event
+                pub fn listen(&mut self, event: Event) {
+                    self.qei.inten.modify(|r, w| unsafe { w.bits(r.bits() | event.mask()) });
+                }
+
+                /// Stops listening for an interrupt event
+                pub fn unlisten(&mut self, event: Event) {
+                    self.qei.inten.modify(|r, w| unsafe { w.bits(r.bits() & !event.mask()) });
+                }
+
+                /// Returns whether an event has occurred since it was last
+                /// cleared, whether or not it is being listened for
+                pub fn is_pending(&self, event: Event) -> bool {
+                    self.qei.ris.read().bits() & event.mask() != 0
+                }
+
+                /// Clears an interrupt event
+                pub fn clear_interrupt(&mut self, event: Event) {
+                    self.qei.isc.write(|w| unsafe { w.bits(event.mask()) });
+                }
+            }
+
+            impl<PINS> hal::Qei for Qei<$QEIX, PINS> {
+                type Count = u32;
+
+                fn count(&self) -> u32 {
+                    self.qei.pos.read().bits()
+                }
+
+                fn direction(&self) -> Direction {
+                    if self.qei.stat.read().direction().bit_is_set() {
+                        Direction::Downcounting
+                    } else {
+                        Direction::Upcounting
+                    }
+                }
+            }
+        )+
+    }
+}
@@ -0,0 +1,175 @@
+//! Common ROM API code for TM4C123 and TM4C129
+//!
+//! The mask ROM holds the TivaWare peripheral driver library and the serial
+//! bootloader. Its functions are found through the ROM API table, whose
+//! entries each point to a table of functions for one peripheral. The
+//! indexes here match TivaWare's `rom.h`.
+
+/// The address of the ROM API table
+pub const ROM_APITABLE: u32 = 0x0100_0010;
+
+/// The per-peripheral tables in the ROM API table
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Table {
+    /// UART functions
+    Uart = 1,
+    /// SSI functions
+    Ssi = 2,
+    /// I2C functions
+    I2c = 3,
+    /// GPIO functions
+    Gpio = 4,
+    /// ADC functions
+    Adc = 5,
+    /// Analog comparator functions
+    Comparator = 6,
+    /// Flash functions
+    Flash = 7,
+    /// PWM functions
+    Pwm = 8,
+    /// QEI functions
+    Qei = 9,
+    /// SysTick functions
+    SysTick = 10,
+    /// Timer functions
+    Timer = 11,
+    /// Watchdog functions
+    Watchdog = 12,
+    /// System control functions
+    SysCtl = 13,
+    /// Interrupt controller functions
+    Interrupt = 14,
+    /// USB functions
+    Usb = 16,
+    /// uDMA functions
+    UDma = 17,
+    /// CAN functions
+    Can = 18,
+    /// Hibernation module functions
+    Hibernate = 19,
+    /// MPU functions
+    Mpu = 20,
+    /// Software utilities, such as CRCs
+    Software = 21,
+    /// EEPROM functions
+    Eeprom = 24,
+    /// FPU functions
+    Fpu = 26,
+}
+
+/// Error returned by the ROM flash functions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlashError;
+
+/// Returns the address of entry `index` of a ROM table. The caller is
+/// responsible for calling it with the right signature.
+pub fn function(table: Table, index: u32) -> usize {
+    unsafe {
+        let table = core::ptr::read_volatile((ROM_APITABLE + table as u32 * 4) as *const u32);
+        core::ptr::read_volatile((table + index * 4) as *const u32) as usize
+    }
+}
+
+/// Returns the ROM version (the first entry in the ROM API table)
+pub fn version() -> u32 {
+    unsafe { core::ptr::read_volatile(ROM_APITABLE as *const u32) }
+}
+
+/// Delays for `count` iterations of a three instruction loop (ROM_SysCtlDelay)
+///
+/// Running from ROM, the timing is not affected by flash wait states.
+pub fn sys_ctl_delay(count: u32) {
+    let f: extern "C" fn(u32) = unsafe { core::mem::transmute(function(Table::SysCtl, 34)) };
+    f(count)
+}
+
+/// Programs words of flash at `address`, which must be word aligned
+/// (ROM_FlashProgram)
+///
+/// # Safety
+///
+/// The caller must have exclusive access to the flash controller, and must
+/// not overwrite the code that is running.
+pub unsafe fn flash_program(data: &[u32], address: u32) -> Result<(), FlashError> {
+    let f: extern "C" fn(*const u32, u32, u32) -> i32 =
+        core::mem::transmute(function(Table::Flash, 0));
+    match f(data.as_ptr(), address, data.len() as u32 * 4) {
+        0 => Ok(()),
+        _ => Err(FlashError),
+    }
+}
+
+/// Erases the flash page at `address` (ROM_FlashErase)
+///
+/// # Safety
+///
+/// The caller must have exclusive access to the flash controller, and must
+/// not erase the code that is running.
+pub unsafe fn flash_erase(address: u32) -> Result<(), FlashError> {
+    let f: extern "C" fn(u32) -> i32 = core::mem::transmute(function(Table::Flash, 3));
+    match f(address) {
+        0 => Ok(()),
+        _ => Err(FlashError),
+    }
+}
+
+/// Continues a CRC-16 (polynomial 0x8005, as used by Modbus and USB) over
+/// `data` (ROM_Crc16). Start with 0.
+pub fn crc16(crc: u16, data: &[u8]) -> u16 {
+    let f: extern "C" fn(u16, *const u8, u32) -> u16 =
+        unsafe { core::mem::transmute(function(Table::Software, 3)) };
+    f(crc, data.as_ptr(), data.len() as u32)
+}
+
+/// Computes the CRC-16 of a buffer of words (ROM_Crc16Array)
+pub fn crc16_words(data: &[u32]) -> u16 {
+    let f: extern "C" fn(u32, *const u32) -> u16 =
+        unsafe { core::mem::transmute(function(Table::Software, 1)) };
+    f(data.len() as u32, data.as_ptr())
+}
+
+/// Continues a CRC-8-CCITT over `data` (ROM_Crc8CCITT). Start with 0.
+pub fn crc8_ccitt(crc: u8, data: &[u8]) -> u8 {
+    let f: extern "C" fn(u8, *const u8, u32) -> u8 =
+        unsafe { core::mem::transmute(function(Table::Software, 4)) };
+    f(crc, data.as_ptr(), data.len() as u32)
+}
+
+/// Starts the ROM bootloader on UART0 (ROM_UpdateUART)
+///
+/// UART0 and its pins must already be configured, including the baud rate.
+/// The bootloader resets the chip when the update is complete.
+pub fn update_uart() -> ! {
+    let f: extern "C" fn() -> ! = unsafe { core::mem::transmute(function(Table::Uart, 21)) };
+    f()
+}
+
+/// Starts the ROM bootloader on SSI0 (ROM_UpdateSSI)
+///
+/// SSI0 and its pins must already be configured. The bootloader resets the
+/// chip when the update is complete.
+pub fn update_ssi() -> ! {
+    let f: extern "C" fn() -> ! = unsafe { core::mem::transmute(function(Table::Ssi, 11)) };
+    f()
+}
+
+/// Starts the ROM bootloader on I2C0 (ROM_UpdateI2C)
+///
+/// I2C0 and its pins must already be configured. The bootloader resets the
+/// chip when the update is complete.
+pub fn update_i2c() -> ! {
+    let f: extern "C" fn() -> ! = unsafe { core::mem::transmute(function(Table::I2c, 12)) };
+    f()
+}
+
+/// Starts the ROM bootloader on USB0 as a DFU device (ROM_UpdateUSB)
+///
+/// `descriptor_info` points to the USB descriptors to use, or is null for
+/// the ROM's defaults. The PLL must be running and the USB0DP and USB0DM
+/// pins must be configured. The bootloader resets the chip when the update
+/// is complete.
+pub fn update_usb(descriptor_info: *const u8) -> ! {
+    let f: extern "C" fn(*const u8) -> ! =
+        unsafe { core::mem::transmute(function(Table::Usb, 35)) };
+    f(descriptor_info)
+}
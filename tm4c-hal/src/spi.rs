@@ -0,0 +1,516 @@
+//! Common SPI code for TM4C123 and TM4C129
+//!
+//! The chip crates implement `embedded_hal_1::spi::SpiBus` for their `Spi`
+//! types. The devices here put a chip-select pin around a bus to give an
+//! `embedded_hal_1::spi::SpiDevice`, either owning the bus or sharing it
+//! with other devices through a `Mutex<RefCell<_>>`.
+//!
+//! `Dividers` works out the bit rate settings for both chips.
+
+use crate::{sysctl::Clocks, time::Hertz};
+use core::cell::RefCell;
+use cortex_m::interrupt::Mutex;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal_1::spi::{self, ErrorKind, Operation, SpiBus};
+
+/// The largest clock prescale divisor (CPSDVSR)
+const MAX_CPSDVSR: u32 = 254;
+
+/// The number of serial clock rate (SCR) settings
+const SCR_STEPS: u32 = 256;
+
+/// Error from the bit rate divider solver
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockError {
+    /// The bit rate is below the input clock / (254 * 256)
+    TooSlow,
+}
+
+/// SSI bit rate dividers, giving
+/// SSInClk = input / (CPSDVSR * (1 + SCR))
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dividers {
+    cpsdvsr: u8,
+    scr: u8,
+}
+
+impl Dividers {
+    /// Finds the dividers for the fastest bit rate that doesn't exceed
+    /// `freq`. Rates above half the input clock give half the input clock.
+    pub fn new(input: Hertz, freq: Hertz) -> Result<Self, ClockError> {
+        if freq.0 == 0 {
+            return Err(ClockError::TooSlow);
+        }
+        // The smallest total divisor that is slow enough
+        let wanted = input.0.div_ceil(freq.0);
+
+        let mut best: Option<(u32, Dividers)> = None;
+        for cpsdvsr in (2..=MAX_CPSDVSR).step_by(2) {
+            let steps = wanted.div_ceil(cpsdvsr).max(1);
+            if steps > SCR_STEPS {
+                continue;
+            }
+            let divisor = cpsdvsr * steps;
+            let better = match best {
+                Some((best_divisor, _)) => divisor < best_divisor,
+                None => true,
+            };
+            if better {
+                best = Some((
+                    divisor,
+                    Dividers {
+                        cpsdvsr: cpsdvsr as u8,
+                        scr: (steps - 1) as u8,
+                    },
+                ));
+            }
+            if divisor == wanted {
+                break;
+            }
+        }
+        best.map(|(_, dividers)| dividers)
+            .ok_or(ClockError::TooSlow)
+    }
+
+    /// Reads back the dividers from the SSICPSR and SSICR0 fields. Used by
+    /// the chip crates.
+    #[doc(hidden)]
+    pub fn from_registers(cpsdvsr: u8, scr: u8) -> Self {
+        Dividers { cpsdvsr, scr }
+    }
+
+    /// Returns the clock prescale divisor (CPSDVSR): even, from 2 to 254
+    pub fn cpsdvsr(&self) -> u8 {
+        self.cpsdvsr
+    }
+
+    /// Returns the serial clock rate (SCR), from 0 to 255
+    pub fn scr(&self) -> u8 {
+        self.scr
+    }
+
+    /// Returns the bit rate these dividers give from `input`, rounded down.
+    /// A CPSDVSR of zero, as read from an unconfigured SSI, gives 0 Hz.
+    pub fn frequency(&self, input: Hertz) -> Hertz {
+        match u32::from(self.cpsdvsr) {
+            0 => Hertz(0),
+            cpsdvsr => Hertz(input.0 / (cpsdvsr * (u32::from(self.scr) + 1))),
+        }
+    }
+}
+
+/// Error from an SPI device
+#[derive(Debug)]
+pub enum DeviceError<SPI, CS> {
+    /// The bus failed
+    Spi(SPI),
+    /// The chip-select pin couldn't be driven
+    Cs(CS),
+}
+
+impl<SPI, CS> spi::Error for DeviceError<SPI, CS>
+where
+    SPI: spi::Error,
+    CS: core::fmt::Debug,
+{
+    fn kind(&self) -> ErrorKind {
+        match self {
+            DeviceError::Spi(e) => e.kind(),
+            DeviceError::Cs(_) => ErrorKind::ChipSelectFault,
+        }
+    }
+}
+
+/// Runs a transaction with `cs` held low. The bus is flushed before `cs` is
+/// released, so the last frame is never cut short, and `cs` is released even
+/// if the bus fails. A bus error is returned in preference to an error
+/// releasing `cs`.
+fn transaction<BUS, CS, Word>(
+    bus: &mut BUS,
+    cs: &mut CS,
+    sysclk: Hertz,
+    operations: &mut [Operation<'_, Word>],
+) -> Result<(), DeviceError<BUS::Error, CS::Error>>
+where
+    BUS: SpiBus<Word>,
+    CS: OutputPin,
+    Word: Copy + 'static,
+{
+    cs.set_low().map_err(DeviceError::Cs)?;
+
+    let result = operations.iter_mut().try_for_each(|op| match op {
+        Operation::Read(words) => bus.read(words),
+        Operation::Write(words) => bus.write(words),
+        Operation::Transfer(read, write) => bus.transfer(read, write),
+        Operation::TransferInPlace(words) => bus.transfer_in_place(words),
+        Operation::DelayNs(ns) => {
+            bus.flush()?;
+            delay_ns(sysclk, *ns);
+            Ok(())
+        }
+    });
+    let flushed = bus.flush();
+    let released = cs.set_high();
+
+    result.and(flushed).map_err(DeviceError::Spi)?;
+    released.map_err(DeviceError::Cs)
+}
+
+/// Busy-waits for at least `ns` nanoseconds
+fn delay_ns(sysclk: Hertz, ns: u32) {
+    let cycles = u64::from(ns) * u64::from(sysclk.0) / 1_000_000_000;
+    cortex_m::asm::delay(cycles as u32 + 1);
+}
+
+/// An SPI device with a bus to itself
+pub struct ExclusiveDevice<BUS, CS> {
+    bus: BUS,
+    cs: CS,
+    sysclk: Hertz,
+}
+
+impl<BUS, CS> ExclusiveDevice<BUS, CS>
+where
+    CS: OutputPin,
+{
+    /// Creates a device on `bus`, deselecting it with `cs`. The clocks are
+    /// used to time delay operations.
+    pub fn new(bus: BUS, mut cs: CS, clocks: &Clocks) -> Result<Self, CS::Error> {
+        cs.set_high()?;
+        Ok(ExclusiveDevice {
+            bus,
+            cs,
+            sysclk: clocks.sysclk,
+        })
+    }
+
+    /// Releases the bus and chip-select pin
+    pub fn free(self) -> (BUS, CS) {
+        (self.bus, self.cs)
+    }
+}
+
+impl<BUS, CS> spi::ErrorType for ExclusiveDevice<BUS, CS>
+where
+    BUS: spi::ErrorType,
+    CS: OutputPin,
+    CS::Error: core::fmt::Debug,
+{
+    type Error = DeviceError<BUS::Error, CS::Error>;
+}
+
+impl<BUS, CS, Word> spi::SpiDevice<Word> for ExclusiveDevice<BUS, CS>
+where
+    BUS: SpiBus<Word>,
+    CS: OutputPin,
+    CS::Error: core::fmt::Debug,
+    Word: Copy + 'static,
+{
+    fn transaction(&mut self, operations: &mut [Operation<'_, Word>]) -> Result<(), Self::Error> {
+        transaction(&mut self.bus, &mut self.cs, self.sysclk, operations)
+    }
+}
+
+/// An SPI device on a bus shared with other devices
+///
+/// Each transaction runs in a critical section, so devices used from
+/// interrupt handlers can share the bus too. Interrupts stay disabled for the
+/// whole transaction, including any `DelayNs` operations, so keep
+/// transactions short.
+pub struct SharedDevice<'a, BUS, CS> {
+    bus: &'a Mutex<RefCell<BUS>>,
+    cs: CS,
+    sysclk: Hertz,
+}
+
+impl<'a, BUS, CS> SharedDevice<'a, BUS, CS>
+where
+    CS: OutputPin,
+{
+    /// Creates a device on the shared `bus`, deselecting it with `cs`. The
+    /// clocks are used to time delay operations.
+    pub fn new(
+        bus: &'a Mutex<RefCell<BUS>>,
+        mut cs: CS,
+        clocks: &Clocks,
+    ) -> Result<Self, CS::Error> {
+        cs.set_high()?;
+        Ok(SharedDevice {
+            bus,
+            cs,
+            sysclk: clocks.sysclk,
+        })
+    }
+
+    /// Releases the chip-select pin
+    pub fn free(self) -> CS {
+        self.cs
+    }
+}
+
+impl<BUS, CS> spi::ErrorType for SharedDevice<'_, BUS, CS>
+where
+    BUS: spi::ErrorType,
+    CS: OutputPin,
+    CS::Error: core::fmt::Debug,
+{
+    type Error = DeviceError<BUS::Error, CS::Error>;
+}
+
+impl<BUS, CS, Word> spi::SpiDevice<Word> for SharedDevice<'_, BUS, CS>
+where
+    BUS: SpiBus<Word>,
+    CS: OutputPin,
+    CS::Error: core::fmt::Debug,
+    Word: Copy + 'static,
+{
+    fn transaction(&mut self, operations: &mut [Operation<'_, Word>]) -> Result<(), Self::Error> {
+        let bus = self.bus;
+        let cs = &mut self.cs;
+        let sysclk = self.sysclk;
+        cortex_m::interrupt::free(|token| {
+            let mut bus = bus.borrow(token).borrow_mut();
+            transaction(&mut *bus, cs, sysclk, operations)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal_1::spi::SpiDevice;
+    use std::rc::Rc;
+
+    fn solve(input: u32, freq: u32) -> Result<(Dividers, u32), ClockError> {
+        let dividers = Dividers::new(Hertz(input), Hertz(freq))?;
+        Ok((dividers, dividers.frequency(Hertz(input)).0))
+    }
+
+    #[test]
+    fn exact() {
+        let (dividers, actual) = solve(80_000_000, 1_000_000).unwrap();
+        assert_eq!(actual, 1_000_000);
+        assert_eq!(
+            u32::from(dividers.cpsdvsr) * (u32::from(dividers.scr) + 1),
+            80
+        );
+    }
+
+    #[test]
+    fn rounds_down() {
+        // 120 MHz / 7 MHz is 17.1, so the divisor must be 18
+        let (_, actual) = solve(120_000_000, 7_000_000).unwrap();
+        assert_eq!(actual, 120_000_000 / 18);
+    }
+
+    #[test]
+    fn odd_divisor() {
+        // CPSDVSR is even, so a divisor of 25 can't be made and 26 is next
+        let (dividers, actual) = solve(16_000_000, 16_000_000 / 25).unwrap();
+        assert_eq!(
+            dividers,
+            Dividers {
+                cpsdvsr: 2,
+                scr: 12
+            }
+        );
+        assert_eq!(actual, 16_000_000 / 26);
+    }
+
+    #[test]
+    fn fastest() {
+        let (dividers, actual) = solve(16_000_000, 20_000_000).unwrap();
+        assert_eq!(dividers, Dividers { cpsdvsr: 2, scr: 0 });
+        assert_eq!(actual, 8_000_000);
+    }
+
+    #[test]
+    fn slowest() {
+        let input = 254 * 256 * 100;
+        let (dividers, actual) = solve(input, 100).unwrap();
+        assert_eq!(
+            dividers,
+            Dividers {
+                cpsdvsr: 254,
+                scr: 255
+            }
+        );
+        assert_eq!(actual, 100);
+        assert_eq!(solve(input, 99), Err(ClockError::TooSlow));
+    }
+
+    #[test]
+    fn zero() {
+        assert_eq!(solve(16_000_000, 0), Err(ClockError::TooSlow));
+        let unconfigured = Dividers::from_registers(0, 0);
+        assert_eq!(unconfigured.frequency(Hertz(16_000_000)).0, 0);
+    }
+
+    #[test]
+    fn matches_search() {
+        let input = 120_000_000;
+        for freq in (2_000..60_000_000).step_by(99_991) {
+            let (_, actual) = solve(input, freq).unwrap();
+            assert!(actual <= freq);
+            // No pair of dividers gets closer without going over
+            let mut closest = 0;
+            for cpsdvsr in (2..=254).step_by(2) {
+                for scr in 0..256 {
+                    let divisor = cpsdvsr * (scr + 1);
+                    if u64::from(input) <= u64::from(freq) * u64::from(divisor) {
+                        closest = closest.max(input / divisor);
+                    }
+                }
+            }
+            assert_eq!(actual, closest, "{} Hz", freq);
+        }
+    }
+
+    /// Everything the mock bus and pin have been asked to do
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        CsLow,
+        CsHigh,
+        Write(Vec<u8>),
+        Read(usize),
+        Flush,
+    }
+
+    #[derive(Default)]
+    struct State {
+        events: Vec<Event>,
+        fail_write: bool,
+        fail_cs_high: bool,
+    }
+
+    #[derive(Debug)]
+    struct BusError;
+
+    impl spi::Error for BusError {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    #[derive(Debug)]
+    struct PinError;
+
+    struct MockBus(Rc<RefCell<State>>);
+
+    impl spi::ErrorType for MockBus {
+        type Error = BusError;
+    }
+
+    impl SpiBus<u8> for MockBus {
+        fn read(&mut self, words: &mut [u8]) -> Result<(), BusError> {
+            self.0.borrow_mut().events.push(Event::Read(words.len()));
+            Ok(())
+        }
+
+        fn write(&mut self, words: &[u8]) -> Result<(), BusError> {
+            let mut state = self.0.borrow_mut();
+            if state.fail_write {
+                return Err(BusError);
+            }
+            state.events.push(Event::Write(words.to_vec()));
+            Ok(())
+        }
+
+        fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), BusError> {
+            self.write(write)?;
+            self.read(read)
+        }
+
+        fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), BusError> {
+            self.write(words)?;
+            self.read(words)
+        }
+
+        fn flush(&mut self) -> Result<(), BusError> {
+            self.0.borrow_mut().events.push(Event::Flush);
+            Ok(())
+        }
+    }
+
+    struct MockPin(Rc<RefCell<State>>);
+
+    impl OutputPin for MockPin {
+        type Error = PinError;
+
+        fn set_low(&mut self) -> Result<(), PinError> {
+            self.0.borrow_mut().events.push(Event::CsLow);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), PinError> {
+            let mut state = self.0.borrow_mut();
+            if state.fail_cs_high {
+                return Err(PinError);
+            }
+            state.events.push(Event::CsHigh);
+            Ok(())
+        }
+    }
+
+    fn device() -> (ExclusiveDevice<MockBus, MockPin>, Rc<RefCell<State>>) {
+        let state = Rc::new(RefCell::new(State::default()));
+        let clocks = Clocks {
+            osc: Hertz(16_000_000),
+            sysclk: Hertz(16_000_000),
+        };
+        let device =
+            ExclusiveDevice::new(MockBus(state.clone()), MockPin(state.clone()), &clocks).unwrap();
+        state.borrow_mut().events.clear();
+        (device, state)
+    }
+
+    #[test]
+    fn cs_held_around_transaction() {
+        let (mut device, state) = device();
+        let mut read = [0; 2];
+        device
+            .transaction(&mut [Operation::Write(&[1, 2]), Operation::Read(&mut read)])
+            .unwrap();
+        // Selected before the first word, and flushed before deselecting
+        assert_eq!(
+            state.borrow().events,
+            [
+                Event::CsLow,
+                Event::Write(vec![1, 2]),
+                Event::Read(2),
+                Event::Flush,
+                Event::CsHigh,
+            ]
+        );
+    }
+
+    #[test]
+    fn cs_released_after_bus_error() {
+        let (mut device, state) = device();
+        state.borrow_mut().fail_write = true;
+        let result = device.transaction(&mut [Operation::Write(&[1]), Operation::Read(&mut [0])]);
+        assert!(matches!(result, Err(DeviceError::Spi(BusError))));
+        // The read after the failed write is skipped
+        assert_eq!(
+            state.borrow().events,
+            [Event::CsLow, Event::Flush, Event::CsHigh]
+        );
+    }
+
+    #[test]
+    fn cs_error() {
+        let (mut device, state) = device();
+        state.borrow_mut().fail_cs_high = true;
+        let result = device.transaction(&mut [Operation::Write(&[1])]);
+        assert!(matches!(result, Err(DeviceError::Cs(PinError))));
+    }
+
+    #[test]
+    fn bus_error_wins() {
+        let (mut device, state) = device();
+        state.borrow_mut().fail_write = true;
+        state.borrow_mut().fail_cs_high = true;
+        let result = device.transaction(&mut [Operation::Write(&[1])]);
+        assert!(matches!(result, Err(DeviceError::Spi(BusError))));
+    }
+}
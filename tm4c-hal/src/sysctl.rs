@@ -45,6 +45,76 @@ impl Clocks {
     }
 }
 
+/// A reason for the chip being reset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResetCause {
+    /// Power was applied
+    PowerOn,
+    /// The supply voltage dropped too low
+    BrownOut,
+    /// The RST pin was asserted
+    External,
+    /// Watchdog timer 0 timed out
+    Watchdog0,
+    /// Watchdog timer 1 timed out
+    Watchdog1,
+    /// Software requested a system reset
+    Software,
+    /// The main oscillator failed
+    MainOscillatorFailure,
+    /// The chip woke from hibernation (TM4C129 only; the TM4C123 reports
+    /// this as `PowerOn`)
+    Hibernate,
+}
+
+impl ResetCause {
+    /// Returns the bit for this cause in the RESC register
+    pub fn mask(self) -> u32 {
+        match self {
+            ResetCause::External => 1 << 0,
+            ResetCause::PowerOn => 1 << 1,
+            ResetCause::BrownOut => 1 << 2,
+            ResetCause::Watchdog0 => 1 << 3,
+            ResetCause::Software => 1 << 4,
+            ResetCause::Watchdog1 => 1 << 5,
+            ResetCause::Hibernate => 1 << 6,
+            ResetCause::MainOscillatorFailure => 1 << 16,
+        }
+    }
+}
+
+/// The reset causes recorded in the RESC register. A cause stays recorded
+/// until it is cleared (or until a power-on reset), so several may be
+/// present.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResetCauses(pub u32);
+
+impl ResetCauses {
+    /// Returns whether the given cause is recorded
+    pub fn contains(self, cause: ResetCause) -> bool {
+        self.0 & cause.mask() != 0
+    }
+
+    /// Returns the most specific recorded cause, or `None` if nothing is
+    /// recorded. Clearing the causes after each boot makes sure this is the
+    /// cause of the last reset.
+    pub fn primary(self) -> Option<ResetCause> {
+        [
+            ResetCause::MainOscillatorFailure,
+            ResetCause::Watchdog0,
+            ResetCause::Watchdog1,
+            ResetCause::Software,
+            ResetCause::Hibernate,
+            ResetCause::BrownOut,
+            ResetCause::External,
+            ResetCause::PowerOn,
+        ]
+        .iter()
+        .copied()
+        .find(|&cause| self.contains(cause))
+    }
+}
+
 /// This module is all about identifying the physical chip we're running on.
 pub mod chip_id {
 
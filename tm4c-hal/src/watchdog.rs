@@ -0,0 +1,193 @@
+//! Common watchdog timer code for TM4C123 and TM4C129
+
+/// How the watchdog interrupt is delivered
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InterruptType {
+    /// A standard interrupt, through the NVIC
+    Standard,
+    /// A non-maskable interrupt
+    NonMaskable,
+}
+
+/// Value to write to the lock register to unlock the watchdog
+pub const UNLOCK: u32 = 0x1ACC_E551;
+
+#[macro_export]
+/// Implements the watchdog driver and embedded-hal for a TM4C watchdog timer
+///
+/// `$bit` is the watchdog's bit in the SRWD and PRWD registers. The last
+/// parameter is `true` for a watchdog clocked from PIOSC, whose registers
+/// need the write-complete (WRC) handshake.
+///
+/// The invoking module must have the chip's `SYSCTL` and `bb` in scope.
+macro_rules! watchdog_hal {
+    ($($WDTX:ident: ($powerDomain:ident, $bit:expr, $wdtX:ident, $piosc:expr),)+) => {
+        $(
+            impl Watchdog<$WDTX> {
+                /// Powers up the watchdog, ready to be started. The
+                /// watchdog will reset the chip once started, unless that is
+                /// turned off with `set_reset_enabled`.
+                pub fn $wdtX(wdt: $WDTX, clocks: &Clocks, pc: &sysctl::PowerControl) -> Self {
+                    sysctl::control_power(
+                        pc, sysctl::Domain::$powerDomain,
+                        sysctl::RunMode::Run, sysctl::PowerState::On);
+                    sysctl::reset(pc, sysctl::Domain::$powerDomain);
+
+                    let clock = if $piosc { Hertz(16_000_000) } else { clocks.sysclk };
+                    let mut watchdog = Watchdog { wdt, clock };
+                    watchdog.set_reset_enabled(true);
+                    watchdog
+                }
+
+                /// Releases the watchdog peripheral. A running watchdog keeps
+                /// running.
+                pub fn free(self) -> $WDTX {
+                    self.wdt
+                }
+
+                /// Waits until the previous register write has completed
+                fn wait(&self) {
+                    if $piosc {
+                        while self.wdt.ctl.read().bits() & (1 << 31) == 0 {}
+                    }
+                }
+
+                /// Runs `f` with the registers unlocked, restoring the lock
+                /// afterwards
+                fn unlocked<F>(&mut self, f: F)
+                where
+                    F: FnOnce(&mut Self),
+                {
+                    let locked = self.is_locked();
+                    if locked {
+                        self.unlock();
+                    }
+                    f(self);
+                    if locked {
+                        self.lock();
+                    }
+                }
+
+                /// Locks the watchdog registers, so that stray writes cannot
+                /// change or stop it. The driver unlocks them as needed.
+                pub fn lock(&mut self) {
+                    self.wait();
+                    self.wdt.lock.write(|w| unsafe { w.bits(0) });
+                }
+
+                /// Unlocks the watchdog registers
+                pub fn unlock(&mut self) {
+                    self.wait();
+                    self.wdt.lock.write(|w| unsafe { w.bits(UNLOCK) });
+                }
+
+                /// Returns whether the watchdog registers are locked
+                pub fn is_locked(&self) -> bool {
+                    self.wdt.lock.read().bits() == 1
+                }
+
+                /// Sets whether the watchdog resets the chip when it times
+                /// out for the second time without being fed
+                pub fn set_reset_enabled(&mut self, enabled: bool) {
+                    self.unlocked(|w| {
+                        w.wait();
+                        w.wdt.ctl.modify(|r, c| unsafe {
+                            c.bits(if enabled { r.bits() | 0b010 } else { r.bits() & !0b010 })
+                        });
+                    });
+                }
+
+                /// Sets how the interrupt is delivered on the first time-out.
+                /// This must be set before the watchdog is started.
+                pub fn set_interrupt_type(&mut self, interrupt_type: InterruptType) {
+                    let nmi = interrupt_type == InterruptType::NonMaskable;
+                    self.unlocked(|w| {
+                        w.wait();
+                        w.wdt.ctl.modify(|r, c| unsafe {
+                            c.bits(if nmi { r.bits() | 0b100 } else { r.bits() & !0b100 })
+                        });
+                    });
+                }
+
+                /// Sets whether the watchdog stops counting while the
+                /// processor is halted by the debugger
+                pub fn stall_on_debug(&mut self, stall: bool) {
+                    self.unlocked(|w| {
+                        w.wait();
+                        w.wdt.test.modify(|r, t| unsafe {
+                            t.bits(if stall { r.bits() | 1 << 8 } else { r.bits() & !(1 << 8) })
+                        });
+                    });
+                }
+
+                /// Returns whether the watchdog is running
+                pub fn is_running(&self) -> bool {
+                    self.wdt.ctl.read().bits() & 0b001 != 0
+                }
+
+                /// Returns the number of watchdog clock cycles until the
+                /// next time-out
+                pub fn value(&self) -> u32 {
+                    self.wdt.value.read().bits()
+                }
+
+                /// Returns whether the watchdog has timed out once since it
+                /// was last fed
+                pub fn is_pending(&self) -> bool {
+                    self.wdt.ris.read().bits() & 1 != 0
+                }
+            }
+
+            impl hal::watchdog::Watchdog for Watchdog<$WDTX> {
+                /// Reloads the counter and clears any pending time-out
+                fn feed(&mut self) {
+                    self.unlocked(|w| {
+                        let load = w.wdt.load.read().bits();
+                        w.wait();
+                        w.wdt.load.write(|l| unsafe { l.bits(load) });
+                        w.wait();
+                        w.wdt.icr.write(|i| unsafe { i.bits(1) });
+                    });
+                }
+            }
+
+            impl hal::watchdog::WatchdogEnable for Watchdog<$WDTX> {
+                type Time = MilliSeconds;
+
+                /// Starts the watchdog. It raises its interrupt after
+                /// `period` and, if not fed by then, resets the chip after
+                /// another `period`. Once started it can only be stopped by
+                /// `disable`.
+                fn start<T>(&mut self, period: T)
+                where
+                    T: Into<MilliSeconds>,
+                {
+                    let ticks = u64::from(self.clock.0) * u64::from(period.into().0) / 1000;
+                    let ticks = ticks.max(1).min(u64::from(u32::MAX)) as u32;
+                    self.unlocked(|w| {
+                        w.wait();
+                        w.wdt.load.write(|l| unsafe { l.bits(ticks) });
+                        w.wait();
+                        w.wdt.ctl.modify(|r, c| unsafe { c.bits(r.bits() | 0b001) });
+                    });
+                }
+            }
+
+            impl hal::watchdog::WatchdogDisable for Watchdog<$WDTX> {
+                /// Stops the watchdog. The hardware can only be stopped by
+                /// resetting the peripheral, so this also clears the rest of
+                /// its configuration (and the reset is re-enabled).
+                fn disable(&mut self) {
+                    // We own the peripheral, so nothing else is using its
+                    // reset bit, and bit-banding makes the writes atomic
+                    let p = unsafe { &*SYSCTL::ptr() };
+                    unsafe {
+                        bb::toggle_bit(&p.srwd, $bit);
+                        bb::spin_bit(&p.prwd, $bit);
+                    }
+                    self.set_reset_enabled(true);
+                }
+            }
+        )+
+    }
+}
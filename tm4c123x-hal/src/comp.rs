@@ -0,0 +1,49 @@
+//! Analog comparators
+//!
+//! Input pins must be put in tri-state (with `into_tri_state`), which
+//! disconnects the digital input.
+
+use crate::{
+    gpio::{gpioc, gpiof, AlternateFunction, OutputMode, Tristate, AF9},
+    sysctl, Sealed,
+};
+use core::marker::PhantomData;
+
+use tm4c123x::COMP;
+
+pub use tm4c_hal::comp::{LadderRange, Sense};
+use tm4c_hal::{comp_hal, comp_pins};
+
+/// Comparator 0 (type state)
+pub struct C0;
+/// Comparator 1 (type state)
+pub struct C1;
+
+/// Implemented for anything that can be the positive input of a comparator
+pub trait PositiveInput<C>: Sealed {
+    /// Returns the value for the ACCTLn ASRCP field
+    #[doc(hidden)]
+    fn asrcp(&self) -> u8;
+}
+
+/// Implemented for any pin that can be the negative input of a comparator
+pub trait NegativeInput<C>: Sealed {}
+
+/// Implemented for any pin that can be the output of a comparator
+pub trait OutputPin<C>: Sealed {}
+
+comp_pins!(C0,
+    plus: [(gpioc::PC6, 0)],
+    minus: [gpioc::PC7],
+    output: [(gpiof::PF0, AF9)],
+);
+comp_pins!(C1,
+    plus: [(gpioc::PC5, 0), (gpioc::PC6, 1)],
+    minus: [gpioc::PC4],
+    output: [(gpiof::PF1, AF9)],
+);
+
+comp_hal!(COMP, [
+    C0: (comp0, acctl0, acstat0, 0),
+    C1: (comp1, acctl1, acstat1, 1),
+]);
@@ -0,0 +1,29 @@
+//! Internal flash memory
+//!
+//! The flash is erased in 1 KiB pages and programmed in 32-bit words. The
+//! program is running from this flash, so take care to only erase and write
+//! the spare space above it.
+//!
+//! Blocks can be protected in 2 KiB units with `Flash::protect`, and the
+//! four user registers hold application data. Both kinds of change only last
+//! until reset, unless the `Uncommitted` they return is committed.
+
+pub use tm4c_hal::flash::{Error, Protection, UserRegister, WRITE_BUFFER_WORDS};
+
+use tm4c123x::FLASH_CTRL;
+use tm4c_hal::{
+    flash::{check_range, check_status, USER_REG_COMMIT},
+    flash_hal,
+};
+
+/// Returns the key that must accompany writes to FMC and FMC2
+fn write_key(flash: &FLASH_CTRL) -> u32 {
+    let key = if flash.bootcfg.read().key().bit_is_set() {
+        0xA442
+    } else {
+        0x71D5
+    };
+    key << 16
+}
+
+flash_hal!(FLASH_CTRL, 1024, fsize, 2048, 1);
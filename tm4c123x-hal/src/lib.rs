@@ -24,7 +24,7 @@
 #![no_std]
 
 pub use tm4c123x::{self, CorePeripherals, Peripherals};
-pub use tm4c_hal::{bb, delay, time};
+pub use tm4c_hal::{bb, delay, kvstore, time};
 
 // Enable use of interrupt macro
 #[cfg(feature = "rt")]
@@ -40,13 +40,20 @@ mod sealed {
     impl Sealed for () {}
 }
 
+pub mod boot;
+pub mod comp;
 pub mod eeprom;
+pub mod flash;
 pub mod gpio;
 pub mod hib;
 pub mod i2c;
+pub mod mcpwm;
 pub mod prelude;
 pub mod pwm;
+pub mod qei;
+pub mod rom;
 pub mod serial;
 pub mod spi;
 pub mod sysctl;
 pub mod timer;
+pub mod watchdog;
@@ -0,0 +1,118 @@
+//! Motion Control PWM modules (M0PWM and M1PWM)
+//!
+//! Unlike the timer based PWM in the `pwm` module, each of these modules has
+//! four generators with two outputs each, can count up/down for
+//! centre-aligned pulses and can drive complementary output pairs.
+//!
+//! Note that on the TM4C123 the PWM clock divider lives in the SYSCTL RCC
+//! register, so it is shared by both PWM modules. Splitting one module
+//! changes the clock of the other.
+
+pub use tm4c_hal::mcpwm::*;
+
+use crate::{
+    bb,
+    gpio::{gpioa, gpiob, gpioc, gpiod, gpioe, gpiof, AlternateFunction, OutputMode, AF4, AF5},
+    hal::PwmPin,
+    sysctl::{self, Clocks},
+    time::Hertz,
+    Sealed,
+};
+use core::marker::PhantomData;
+use tm4c123x::{PWM0, PWM1};
+use tm4c_hal::mcpwm_hal;
+
+/// Implemented for any pin that can be output A of a PWM generator
+pub trait PinA<PWM, GEN>: Sealed {}
+
+/// Implemented for any pin that can be output B of a PWM generator
+pub trait PinB<PWM, GEN>: Sealed {}
+
+/// Implemented for any pin that can be a fault input of a PWM module
+pub trait FaultPin<PWM>: Sealed {
+    /// Returns which fault input this pin is
+    #[doc(hidden)]
+    fn input(&self) -> FaultInput;
+}
+
+macro_rules! pwm_pins {
+    ($PWMn:ident, $AF:ident, $GEN:ident, a: [$($($pina:ident)::+),*], b: [$($($pinb:ident)::+),*]) => {
+        $(
+            impl<T> PinA<$PWMn, $GEN> for $($pina)::+<AlternateFunction<$AF, T>> where T: OutputMode {}
+        )*
+        $(
+            impl<T> PinB<$PWMn, $GEN> for $($pinb)::+<AlternateFunction<$AF, T>> where T: OutputMode {}
+        )*
+    };
+}
+
+pwm_pins!(PWM0, AF4, G0, a: [gpiob::PB6], b: [gpiob::PB7]);
+pwm_pins!(PWM0, AF4, G1, a: [gpiob::PB4], b: [gpiob::PB5]);
+pwm_pins!(PWM0, AF4, G2, a: [gpioe::PE4], b: [gpioe::PE5]);
+pwm_pins!(PWM0, AF4, G3, a: [gpioc::PC4, gpiod::PD0], b: [gpioc::PC5, gpiod::PD1]);
+
+pwm_pins!(PWM1, AF5, G0, a: [gpiod::PD0], b: [gpiod::PD1]);
+pwm_pins!(PWM1, AF5, G1, a: [gpioa::PA6, gpioe::PE4], b: [gpioa::PA7, gpioe::PE5]);
+pwm_pins!(PWM1, AF5, G2, a: [gpiof::PF0], b: [gpiof::PF1]);
+pwm_pins!(PWM1, AF5, G3, a: [gpiof::PF2], b: [gpiof::PF3]);
+
+macro_rules! fault_pins {
+    ($PWMn:ident, $AF:ident, $input:ident: [$($($pin:ident)::+),*]) => {
+        $(
+            impl<T> FaultPin<$PWMn> for $($pin)::+<AlternateFunction<$AF, T>>
+            where
+                T: OutputMode,
+            {
+                fn input(&self) -> FaultInput {
+                    FaultInput::$input
+                }
+            }
+        )*
+    };
+}
+
+fault_pins!(PWM0, AF4, Fault0: [gpiod::PD2, gpiod::PD6, gpiof::PF2]);
+fault_pins!(PWM1, AF5, Fault0: [gpiof::PF4]);
+
+/// Sets the PWM clock divider, which is shared by both modules on this chip
+fn set_clock_divider<PWM>(_pwm: &PWM, divider: ClockDivider) {
+    // We only touch the PWM fields, and do so inside a critical section
+    let p = unsafe { &*tm4c123x::SYSCTL::ptr() };
+    cortex_m::interrupt::free(|_| {
+        p.rcc.modify(|_, w| match divider.pwmdiv() {
+            Some(div) => unsafe { w.usepwmdiv().set_bit().pwmdiv().bits(div) },
+            None => w.usepwmdiv().clear_bit(),
+        });
+    });
+}
+
+mcpwm_hal! {
+    PWM0: (Pwm0, [
+        G0: (_0_ctl, _0_load, _0_count, _0_cmpa, _0_cmpb, _0_gena, _0_genb,
+            _0_dbctl, _0_dbrise, _0_dbfall, _0_fltsrc0, _0_minfltper, _0_fltstat0,
+            _0_inten, _0_ris, _0_isc, _0_fltsen; 0),
+        G1: (_1_ctl, _1_load, _1_count, _1_cmpa, _1_cmpb, _1_gena, _1_genb,
+            _1_dbctl, _1_dbrise, _1_dbfall, _1_fltsrc0, _1_minfltper, _1_fltstat0,
+            _1_inten, _1_ris, _1_isc, _1_fltsen; 1),
+        G2: (_2_ctl, _2_load, _2_count, _2_cmpa, _2_cmpb, _2_gena, _2_genb,
+            _2_dbctl, _2_dbrise, _2_dbfall, _2_fltsrc0, _2_minfltper, _2_fltstat0,
+            _2_inten, _2_ris, _2_isc; 2),
+        G3: (_3_ctl, _3_load, _3_count, _3_cmpa, _3_cmpb, _3_gena, _3_genb,
+            _3_dbctl, _3_dbrise, _3_dbfall, _3_fltsrc0, _3_minfltper, _3_fltstat0,
+            _3_inten, _3_ris, _3_isc; 3),
+    ]),
+    PWM1: (Pwm1, [
+        G0: (_0_ctl, _0_load, _0_count, _0_cmpa, _0_cmpb, _0_gena, _0_genb,
+            _0_dbctl, _0_dbrise, _0_dbfall, _0_fltsrc0, _0_minfltper, _0_fltstat0,
+            _0_inten, _0_ris, _0_isc, _0_fltsen; 0),
+        G1: (_1_ctl, _1_load, _1_count, _1_cmpa, _1_cmpb, _1_gena, _1_genb,
+            _1_dbctl, _1_dbrise, _1_dbfall, _1_fltsrc0, _1_minfltper, _1_fltstat0,
+            _1_inten, _1_ris, _1_isc, _1_fltsen; 1),
+        G2: (_2_ctl, _2_load, _2_count, _2_cmpa, _2_cmpb, _2_gena, _2_genb,
+            _2_dbctl, _2_dbrise, _2_dbfall, _2_fltsrc0, _2_minfltper, _2_fltstat0,
+            _2_inten, _2_ris, _2_isc; 2),
+        G3: (_3_ctl, _3_load, _3_count, _3_cmpa, _3_cmpb, _3_gena, _3_genb,
+            _3_dbctl, _3_dbrise, _3_dbfall, _3_fltsrc0, _3_minfltper, _3_fltstat0,
+            _3_inten, _3_ris, _3_isc; 3),
+    ]),
+}
@@ -1,6 +1,28 @@
 //! PWM abstractions
+//!
+//! In PWM mode the prescaler extends the counter, so the halves of the
+//! 16/32-bit timers have 24-bit periods and the halves of the 32/64-bit wide
+//! timers have 32-bit periods. Periods and duty cycles are in system clock
+//! cycles.
 
-use crate::gpio::{gpiob, gpioc, gpiof, AlternateFunction, PushPull, AF7};
+use crate::{
+    gpio::{gpiob, gpioc, gpiod, gpiof, AlternateFunction, PushPull, AF7},
+    sysctl::Clocks,
+    time::Hertz,
+    timer::Synchronize,
+    Sealed,
+};
+
+/// The edges of the PWM output which raise an interrupt
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    /// Rising edges only
+    Rising = 0,
+    /// Falling edges only
+    Falling = 1,
+    /// Both edges
+    Both = 3,
+}
 
 /// a timer
 pub struct Timer<T> {
@@ -44,9 +66,9 @@ macro_rules! into_one_half {
 }
 
 macro_rules! impl_for_timer {
-    ($Name:ident, $timer:path, $domain:expr, even: [$($($even_pins:ident)::+),+],
-            odd: [$($($odd_pins:ident)::+),+]) => {
-        impl_pwm!($timer);
+    ($Name:ident, $timer:path, $module:ident, $domain:expr, $sync_shift:expr, $width:expr,
+            even: [$($($even_pins:ident)::+),+], odd: [$($($odd_pins:ident)::+),+]) => {
+        impl_pwm!($timer, $sync_shift, $width);
 
         $(
             impl EvenPin<Timer<$timer>> for $($even_pins)::+<AlternateFunction<AF7, PushPull>> {}
@@ -68,7 +90,7 @@ macro_rules! impl_for_timer {
                 crate::sysctl::reset(power_control, $domain);
                 timer
                     .cfg
-                    .modify(|_r, w| w.cfg().variant(tm4c123x::timer0::cfg::CFG_A::_16_BIT));
+                    .modify(|_r, w| w.cfg().variant(tm4c123x::$module::cfg::CFG_A::_16_BIT));
                 Timer { timer }
             }
             into_one_half!(
@@ -82,7 +104,7 @@ macro_rules! impl_for_timer {
                 tapwmie,
                 tacdir,
                 taams,
-                tm4c123x::timer0::tamr::TAMR_A
+                tm4c123x::$module::tamr::TAMR_A
             );
             into_one_half!(
                 into_odd,
@@ -95,7 +117,7 @@ macro_rules! impl_for_timer {
                 tbpwmie,
                 tbcdir,
                 tbams,
-                tm4c123x::timer0::tbmr::TBMR_A
+                tm4c123x::$module::tbmr::TBMR_A
             );
             /// Create the PWM implementation for both halves of a timer peripheral
             pub fn into_both<E: EvenPin<Self>, O: OddPin<Self>>(
@@ -116,12 +138,138 @@ macro_rules! impl_for_timer {
 }
 
 macro_rules! pwm_half {
-    ($StructName:ident, $timer:path, $en_bit:expr, $ilr:ident, $matchr:ident) => {
+    ($StructName:ident, $timer:path, $en_bit:expr, $stall_bit:expr, $event_bit:expr,
+            $ilr:ident, $pr:ident, $matchr:ident, $pmr:ident, $mr:ident, $wot:ident, $pwmie:ident,
+            $sync_mask:expr, $width:expr) => {
+        impl Sealed for $StructName<$timer> {}
+
+        impl Synchronize for $StructName<$timer> {
+            fn sync_mask(&self) -> u32 {
+                $sync_mask
+            }
+        }
+
+        impl $StructName<$timer> {
+            /// The longest period, in clock cycles, including the prescaler
+            /// extension
+            const MAX_PERIOD: u64 = (1 << ($width + if $width == 16 { 8 } else { 16 })) - 1;
+
+            /// Reads a value split across an interval/match register and its
+            /// prescaler register
+            fn read_extended(low: u32, high: u32) -> u32 {
+                let mask = ((1u64 << $width) - 1) as u32;
+                let value = u64::from(low & mask) | u64::from(high) << $width;
+                value.min(u64::from(u32::MAX)) as u32
+            }
+
+            /// Returns the value for the interval/match register and its
+            /// prescaler register
+            fn split_extended(value: u32) -> (u32, u32) {
+                let value = u64::from(value).min(Self::MAX_PERIOD);
+                let mask = (1u64 << $width) - 1;
+                ((value & mask) as u32, (value >> $width) as u32)
+            }
+
+            /// Sets whether this half stops counting while the processor is
+            /// halted by the debugger
+            pub fn stall_on_debug(&mut self, stall: bool) {
+                unsafe { crate::bb::change_bit(&self.timer.ctl, $stall_bit, stall) }
+            }
+
+            /// Sets whether this half waits for a trigger before counting.
+            ///
+            /// When enabled, the half is re-armed and does not start counting
+            /// until the previous timer in the daisy chain times out (for the
+            /// odd half, that is the even half of the same timer).
+            pub fn wait_on_trigger(&mut self, enabled: bool) {
+                let was_enabled = crate::bb::read_bit(&self.timer.ctl, $en_bit);
+                // The mode register must only be changed while disabled
+                unsafe { crate::bb::change_bit(&self.timer.ctl, $en_bit, false) };
+                self.timer.$mr.modify(|_, w| w.$wot().bit(enabled));
+                unsafe { crate::bb::change_bit(&self.timer.ctl, $en_bit, was_enabled) };
+            }
+
+            /// Sets the period to match the given frequency as closely as
+            /// possible. The duty cycle is scaled to match the new period.
+            pub fn set_frequency(&mut self, freq: Hertz, clocks: &Clocks) {
+                use embedded_hal::Pwm;
+                let ticks = (clocks.sysclk.0 / freq.0.max(1)).max(2);
+                let duty = u64::from(self.get_duty(()));
+                let old_period = u64::from(self.get_period()).max(1);
+                self.set_period(ticks - 1);
+                let duty = duty * u64::from(self.get_period()) / old_period;
+                self.set_duty((), duty as u32);
+            }
+
+            /// Returns the frequency of the PWM output
+            pub fn frequency(&self, clocks: &Clocks) -> Hertz {
+                use embedded_hal::Pwm;
+                Hertz(clocks.sysclk.0 / self.get_period().saturating_add(1))
+            }
+
+            /// Sets the duty cycle as a percentage. Values over 100 are
+            /// treated as 100.
+            pub fn set_duty_percent(&mut self, percent: u8) {
+                self.set_duty_fraction(u32::from(percent.min(100)), 100);
+            }
+
+            /// Returns the duty cycle as a percentage, rounded down
+            pub fn duty_percent(&self) -> u8 {
+                use embedded_hal::Pwm;
+                let max = u64::from(self.get_max_duty()).max(1);
+                (u64::from(self.get_duty(())) * 100 / max) as u8
+            }
+
+            /// Sets the duty cycle as the fraction `numerator / denominator`.
+            /// Fractions over one are treated as one.
+            pub fn set_duty_fraction(&mut self, numerator: u32, denominator: u32) {
+                use embedded_hal::Pwm;
+                let denominator = u64::from(denominator.max(1));
+                let numerator = u64::from(numerator).min(denominator);
+                let duty = u64::from(self.get_max_duty()) * numerator / denominator;
+                self.set_duty((), duty as u32);
+            }
+
+            /// Starts raising an interrupt on the given edges of the output
+            pub fn listen(&mut self, edge: Edge) {
+                let was_enabled = crate::bb::read_bit(&self.timer.ctl, $en_bit);
+                // The mode register must only be changed while disabled
+                unsafe { crate::bb::change_bit(&self.timer.ctl, $en_bit, false) };
+                let edge = edge as u8;
+                unsafe {
+                    crate::bb::change_bit(&self.timer.ctl, $event_bit, edge & 1 != 0);
+                    crate::bb::change_bit(&self.timer.ctl, $event_bit + 1, edge & 2 != 0);
+                }
+                self.timer.$mr.modify(|_, w| w.$pwmie().set_bit());
+                unsafe { crate::bb::change_bit(&self.timer.imr, $event_bit, true) };
+                unsafe { crate::bb::change_bit(&self.timer.ctl, $en_bit, was_enabled) };
+            }
+
+            /// Stops raising interrupts on the output edges
+            pub fn unlisten(&mut self) {
+                unsafe { crate::bb::change_bit(&self.timer.imr, $event_bit, false) };
+                let was_enabled = crate::bb::read_bit(&self.timer.ctl, $en_bit);
+                unsafe { crate::bb::change_bit(&self.timer.ctl, $en_bit, false) };
+                self.timer.$mr.modify(|_, w| w.$pwmie().clear_bit());
+                unsafe { crate::bb::change_bit(&self.timer.ctl, $en_bit, was_enabled) };
+            }
+
+            /// Returns whether an edge interrupt is pending
+            pub fn is_pending(&self) -> bool {
+                crate::bb::read_bit(&self.timer.ris, $event_bit)
+            }
+
+            /// Clears a pending edge interrupt
+            pub fn clear_interrupt(&mut self) {
+                self.timer.icr.write(|w| unsafe { w.bits(1 << $event_bit) });
+            }
+        }
+
         /// One half of a PWM timer
         impl embedded_hal::Pwm for $StructName<$timer> {
             type Channel = ();
-            type Time = u32; // clock cycles, proper abstraction tbd
-            type Duty = u32; // also clock cycles
+            type Time = u32; // clock cycles, see `set_frequency`
+            type Duty = u32; // also clock cycles, see `set_duty_percent`
 
             fn enable(&mut self, _: ()) {
                 unsafe { crate::bb::change_bit(&self.timer.ctl, $en_bit, true) }
@@ -132,17 +280,23 @@ macro_rules! pwm_half {
             }
 
             fn get_period(&self) -> Self::Time {
-                self.timer.$ilr.read().bits()
+                Self::read_extended(self.timer.$ilr.read().bits(), self.timer.$pr.read().bits())
             }
 
             fn set_period<P: Into<Self::Time>>(&mut self, period: P) {
-                self.timer.$ilr.write(|w| unsafe { w.bits(period.into()) });
+                // The prescaler must be written first, as the interval
+                // register write is what takes effect
+                let (low, high) = Self::split_extended(period.into());
+                self.timer.$pr.write(|w| unsafe { w.bits(high) });
+                self.timer.$ilr.write(|w| unsafe { w.bits(low) });
             }
 
             fn get_duty(&self, _: ()) -> Self::Duty {
-                let thresh = self.timer.$matchr.read().bits();
-                let period = self.get_period();
-                period - thresh
+                let thresh = Self::read_extended(
+                    self.timer.$matchr.read().bits(),
+                    self.timer.$pmr.read().bits(),
+                );
+                self.get_period().saturating_sub(thresh)
             }
 
             fn get_max_duty(&self) -> Self::Duty {
@@ -150,25 +304,58 @@ macro_rules! pwm_half {
             }
 
             fn set_duty(&mut self, _: (), duty: Self::Duty) {
-                self.timer
-                    .$matchr
-                    .write(|w| unsafe { w.bits(self.get_period() - duty) });
+                let (low, high) = Self::split_extended(self.get_period().saturating_sub(duty));
+                self.timer.$pmr.write(|w| unsafe { w.bits(high) });
+                self.timer.$matchr.write(|w| unsafe { w.bits(low) });
             }
         }
     };
 }
 
 macro_rules! impl_pwm {
-    ($timer:path) => {
-        pwm_half!(EvenPWM, $timer, 0, tailr, tamatchr);
-        pwm_half!(OddPWM, $timer, 8, tbilr, tbmatchr);
+    ($timer:path, $sync_shift:expr, $width:expr) => {
+        pwm_half!(
+            EvenPWM,
+            $timer,
+            0,
+            1,
+            2,
+            tailr,
+            tapr,
+            tamatchr,
+            tapmr,
+            tamr,
+            tawot,
+            tapwmie,
+            0b01 << $sync_shift,
+            $width
+        );
+        pwm_half!(
+            OddPWM,
+            $timer,
+            8,
+            9,
+            10,
+            tbilr,
+            tbpr,
+            tbmatchr,
+            tbpmr,
+            tbmr,
+            tbwot,
+            tbpwmie,
+            0b10 << $sync_shift,
+            $width
+        );
     };
 }
 
 impl_for_timer!(
     timer0,
     tm4c123x::TIMER0,
+    timer0,
     crate::sysctl::Domain::Timer0,
+    0,
+    16,
     even: [gpiob::PB6, gpiof::PF0],
     odd: [gpiob::PB7, gpiof::PF1]
 );
@@ -176,7 +363,10 @@ impl_for_timer!(
 impl_for_timer!(
     timer1,
     tm4c123x::TIMER1,
+    timer0,
     crate::sysctl::Domain::Timer1,
+    2,
+    16,
     even: [gpiof::PF2, gpiob::PB4],
     odd: [gpiof::PF3, gpiob::PB5]
 );
@@ -184,7 +374,10 @@ impl_for_timer!(
 impl_for_timer!(
     timer2,
     tm4c123x::TIMER2,
+    timer0,
     crate::sysctl::Domain::Timer2,
+    4,
+    16,
     even: [gpiof::PF4, gpiob::PB0],
     odd: [gpiob::PB1]
 );
@@ -192,7 +385,10 @@ impl_for_timer!(
 impl_for_timer!(
     timer3,
     tm4c123x::TIMER3,
+    timer0,
     crate::sysctl::Domain::Timer3,
+    6,
+    16,
     even: [gpiob::PB2],
     odd: [gpiob::PB3]
 );
@@ -200,7 +396,10 @@ impl_for_timer!(
 impl_for_timer!(
     timer4,
     tm4c123x::TIMER4,
+    timer0,
     crate::sysctl::Domain::Timer4,
+    8,
+    16,
     even: [gpioc::PC0],
     odd: [gpioc::PC1]
 );
@@ -208,7 +407,76 @@ impl_for_timer!(
 impl_for_timer!(
     timer5,
     tm4c123x::TIMER5,
+    timer0,
     crate::sysctl::Domain::Timer5,
+    10,
+    16,
     even: [gpioc::PC2],
     odd: [gpioc::PC3]
 );
+
+impl_for_timer!(
+    wtimer0,
+    tm4c123x::WTIMER0,
+    wtimer0,
+    crate::sysctl::Domain::WideTimer0,
+    12,
+    32,
+    even: [gpioc::PC4],
+    odd: [gpioc::PC5]
+);
+
+impl_for_timer!(
+    wtimer1,
+    tm4c123x::WTIMER1,
+    wtimer0,
+    crate::sysctl::Domain::WideTimer1,
+    14,
+    32,
+    even: [gpioc::PC6],
+    odd: [gpioc::PC7]
+);
+
+impl_for_timer!(
+    wtimer2,
+    tm4c123x::WTIMER2,
+    wtimer0,
+    crate::sysctl::Domain::WideTimer2,
+    16,
+    32,
+    even: [gpiod::PD0],
+    odd: [gpiod::PD1]
+);
+
+impl_for_timer!(
+    wtimer3,
+    tm4c123x::WTIMER3,
+    wtimer0,
+    crate::sysctl::Domain::WideTimer3,
+    18,
+    32,
+    even: [gpiod::PD2],
+    odd: [gpiod::PD3]
+);
+
+impl_for_timer!(
+    wtimer4,
+    tm4c123x::WTIMER4,
+    wtimer0,
+    crate::sysctl::Domain::WideTimer4,
+    20,
+    32,
+    even: [gpiod::PD4],
+    odd: [gpiod::PD5]
+);
+
+impl_for_timer!(
+    wtimer5,
+    tm4c123x::WTIMER5,
+    wtimer0,
+    crate::sysctl::Domain::WideTimer5,
+    22,
+    32,
+    even: [gpiod::PD6],
+    odd: [gpiod::PD7]
+);
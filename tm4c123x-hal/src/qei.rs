@@ -0,0 +1,47 @@
+//! Quadrature Encoder Interface (QEI)
+
+use crate::{
+    gpio::*,
+    hal::{self, Direction},
+    sysctl, Sealed,
+};
+
+use tm4c123x::{QEI0, QEI1};
+
+pub use tm4c_hal::qei::{CaptureMode, Config, Event, ResetMode, SignalMode, VelocityDivider};
+use tm4c_hal::{qei_hal, qei_pins};
+
+/// Quadrature encoder interface
+pub struct Qei<QEI, PINS> {
+    /// Underlying QEI peripheral
+    pub qei: QEI,
+    /// Underlying GPIO pins used by peripheral
+    pub pins: PINS,
+}
+
+/// PhA pin
+pub trait PhAPin<QEI>: Sealed {}
+
+/// PhB pin
+pub trait PhBPin<QEI>: Sealed {}
+
+/// Index pin
+pub trait IdxPin<QEI>: Sealed {}
+
+impl<QEI> IdxPin<QEI> for () {}
+
+qei_pins!(QEI0,
+    pha: [(gpiod::PD6, AF6), (gpiof::PF0, AF6)],
+    phb: [(gpiod::PD7, AF6), (gpiof::PF1, AF6)],
+    idx: [(gpiod::PD3, AF6), (gpiof::PF4, AF6)],
+);
+qei_pins!(QEI1,
+    pha: [(gpioc::PC5, AF6)],
+    phb: [(gpioc::PC6, AF6)],
+    idx: [(gpioc::PC4, AF6)],
+);
+
+qei_hal! {
+    QEI0: (Qei0, qei0),
+    QEI1: (Qei1, qei1),
+}
@@ -0,0 +1,6 @@
+//! ROM API functions
+//!
+//! These call the TivaWare driver library and bootloader held in the mask
+//! ROM, saving flash space.
+
+pub use tm4c_hal::rom::*;
@@ -1,12 +1,17 @@
 //! Serial Peripheral Interface (SPI) bus
+//!
+//! `Spi` also implements the embedded-hal 1.0 `SpiBus`. Wrap it in an
+//! `ExclusiveDevice`, or share it between `SharedDevice`s, to get an
+//! `SpiDevice` that drives a chip-select pin.
 
 pub use crate::hal::spi::{Mode, MODE_0, MODE_1, MODE_2, MODE_3};
+pub use tm4c_hal::spi::{ClockError, DeviceError, Dividers, ExclusiveDevice, SharedDevice};
 
 use crate::{
     gpio::{
-        gpioa::{PA2, PA4, PA5},
-        gpiob::{PB4, PB6, PB7},
-        gpiod::{PD0, PD2, PD3},
+        gpioa::{PA2, PA3, PA4, PA5},
+        gpiob::{PB4, PB5, PB6, PB7},
+        gpiod::{PD0, PD1, PD2, PD3},
         AlternateFunction, OutputMode, AF1, AF2,
     },
     hal::spi::{FullDuplex, Phase, Polarity},
@@ -20,9 +25,32 @@ use tm4c123x::{SSI0, SSI1, SSI2, SSI3};
 
 /// SPI error
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
-    #[doc(hidden)]
-    _Extensible,
+    /// The data size must be between 4 and 16 bits
+    InvalidDataSize,
+    /// The bit rate can't be reached from the input clock
+    Clock(ClockError),
+}
+
+impl embedded_hal_1::spi::Error for Error {
+    fn kind(&self) -> embedded_hal_1::spi::ErrorKind {
+        embedded_hal_1::spi::ErrorKind::Other
+    }
+}
+
+/// Frame format
+#[derive(Clone, Copy, PartialEq)]
+pub enum FrameFormat {
+    /// Freescale SPI, with the given clock polarity and phase. SSInFss is
+    /// low during each frame.
+    Spi(Mode),
+    /// TI synchronous serial: SSInFss pulses high for one clock before
+    /// each frame.
+    Ti,
+    /// National Microwire: an 8-bit command is sent, then a 4 to 16-bit
+    /// reply is received. SSInFss is low for the whole exchange.
+    Microwire,
 }
 
 /// SCK pin
@@ -32,27 +60,65 @@ pub trait SckPin<SPI>: Sealed {}
 pub trait MisoPin<SPI>: Sealed {}
 
 /// MOSI pin
-pub trait MosiPin<SPI>: Sealed {}
+pub trait MosiPin<SPI>: Sealed {
+    #[doc(hidden)]
+    fn connect(&mut self, connected: bool);
+}
+
+/// Frame select pin (SSInFss)
+pub trait FssPin<SPI>: Sealed {}
 
 // SSI0
 impl<T> SckPin<SSI0> for PA2<AlternateFunction<AF2, T>> where T: OutputMode {}
 impl<T> MisoPin<SSI0> for PA4<AlternateFunction<AF2, T>> where T: OutputMode {}
-impl<T> MosiPin<SSI0> for PA5<AlternateFunction<AF2, T>> where T: OutputMode {}
+impl<T> MosiPin<SSI0> for PA5<AlternateFunction<AF2, T>>
+where
+    T: OutputMode,
+{
+    fn connect(&mut self, connected: bool) {
+        self.set_connected(connected)
+    }
+}
+impl<T> FssPin<SSI0> for PA3<AlternateFunction<AF2, T>> where T: OutputMode {}
 
 // SSI1
 impl<T> SckPin<SSI1> for PD0<AlternateFunction<AF2, T>> where T: OutputMode {}
 impl<T> MisoPin<SSI1> for PD2<AlternateFunction<AF2, T>> where T: OutputMode {}
-impl<T> MosiPin<SSI1> for PD3<AlternateFunction<AF2, T>> where T: OutputMode {}
+impl<T> MosiPin<SSI1> for PD3<AlternateFunction<AF2, T>>
+where
+    T: OutputMode,
+{
+    fn connect(&mut self, connected: bool) {
+        self.set_connected(connected)
+    }
+}
+impl<T> FssPin<SSI1> for PD1<AlternateFunction<AF2, T>> where T: OutputMode {}
 
 // SSI2
 impl<T> SckPin<SSI2> for PB4<AlternateFunction<AF2, T>> where T: OutputMode {}
 impl<T> MisoPin<SSI2> for PB6<AlternateFunction<AF2, T>> where T: OutputMode {}
-impl<T> MosiPin<SSI2> for PB7<AlternateFunction<AF2, T>> where T: OutputMode {}
+impl<T> MosiPin<SSI2> for PB7<AlternateFunction<AF2, T>>
+where
+    T: OutputMode,
+{
+    fn connect(&mut self, connected: bool) {
+        self.set_connected(connected)
+    }
+}
+impl<T> FssPin<SSI2> for PB5<AlternateFunction<AF2, T>> where T: OutputMode {}
 
 // SSI3
 impl<T> SckPin<SSI3> for PD0<AlternateFunction<AF1, T>> where T: OutputMode {}
 impl<T> MisoPin<SSI3> for PD2<AlternateFunction<AF1, T>> where T: OutputMode {}
-impl<T> MosiPin<SSI3> for PD3<AlternateFunction<AF1, T>> where T: OutputMode {}
+impl<T> MosiPin<SSI3> for PD3<AlternateFunction<AF1, T>>
+where
+    T: OutputMode,
+{
+    fn connect(&mut self, connected: bool) {
+        self.set_connected(connected)
+    }
+}
+impl<T> FssPin<SSI3> for PD1<AlternateFunction<AF1, T>> where T: OutputMode {}
 
 /// SPI peripheral operating in full duplex master mode
 pub struct Spi<SPI, PINS> {
@@ -60,6 +126,36 @@ pub struct Spi<SPI, PINS> {
     pins: PINS,
 }
 
+/// SPI peripheral operating in slave mode
+///
+/// The SSI's transmit pin (`MosiPin`) drives the bus's MISO line and its
+/// receive pin (`MisoPin`) reads MOSI.
+pub struct SpiSlave<SPI, PINS> {
+    spi: SPI,
+    pins: PINS,
+}
+
+/// Interrupt events
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// The receive FIFO was full when a frame arrived, so it was lost
+    ReceiveOverrun,
+    /// The receive FIFO has not been read for 32 bit periods since the
+    /// last frame arrived
+    ReceiveTimeout,
+    /// The receive FIFO is half full or more
+    Receive,
+    /// The transmit FIFO is half full or less
+    Transmit,
+}
+
+impl Event {
+    /// Returns the bit for this event in the interrupt registers
+    pub fn mask(self) -> u32 {
+        1 << (self as u32)
+    }
+}
+
 macro_rules! busy_wait {
     ($spi:expr, $flag:ident, $op:ident) => {
         loop {
@@ -75,7 +171,8 @@ macro_rules! hal {
     ($($SPIX:ident: ($powerDomain:ident, $spiX:ident),)+) => {
         $(
             impl<SCK, MISO, MOSI> Spi<$SPIX, (SCK, MISO, MOSI)> {
-                /// Configures the SPI peripheral to operate in full duplex master mode
+                /// Configures the SPI peripheral to operate in full duplex
+                /// master mode, at the fastest bit rate not above `freq`
                 pub fn $spiX<F>(
                     spi: $SPIX,
                     pins: (SCK, MISO, MOSI),
@@ -83,13 +180,15 @@ macro_rules! hal {
                     freq: F,
                     clocks: &Clocks,
                     pc: &sysctl::PowerControl,
-                ) -> Self
+                ) -> Result<Self, Error>
                 where
                     F: Into<Hertz>,
                     SCK: SckPin<$SPIX>,
                     MISO: MisoPin<$SPIX>,
                     MOSI: MosiPin<$SPIX>,
                 {
+                    let dividers = Dividers::new(clocks.sysclk, freq.into()).map_err(Error::Clock)?;
+
                     // power up
                     sysctl::control_power(
                         pc, sysctl::Domain::$powerDomain,
@@ -103,45 +202,23 @@ macro_rules! hal {
                     // set to reset value (0 = use system clock)
                     spi.cc.write(|w| w);
 
-                    // Use Moto/SPI & 8bits data size
-                    let scr: u8;
-                    let mut cpsr = 2u32;
-                    let target_bitrate : u32 = clocks.sysclk.0 / freq.into().0;
-
-                    // Find solution for
-                    // SSInClk = SysClk / (CPSDVSR * (1 + SCR))
-                    // with:
-                    //   CPSDVSR in [2,254]
-                    //   SCR in [0,255]
-
-                    loop {
-                        let scr32 = (target_bitrate / cpsr) - 1;
-                        if scr32 < 255 {
-                            scr = scr32 as u8;
-                            break;
-                        }
-                        cpsr += 2;
-                        assert!(cpsr <= 254);
-                    }
-
-                    let cpsr = cpsr as u8;
-
                     spi.cpsr.write(|w| unsafe {
-                        w.cpsdvsr().bits(cpsr)
+                        w.cpsdvsr().bits(dividers.cpsdvsr())
                     });
 
+                    // Use Moto/SPI & 8bits data size
                     spi.cr0.modify(|_,w| unsafe {
                         w.spo().bit(mode.polarity == Polarity::IdleHigh)
                             .sph().bit(mode.phase == Phase::CaptureOnSecondTransition)
                             .frf().moto()
                             .dss()._8()
-                            .scr().bits(scr)
+                            .scr().bits(dividers.scr())
                     });
 
                     // Enable peripheral
                     spi.cr1.write(|w| w.sse().set_bit());
 
-                    Spi { spi, pins }
+                    Ok(Spi { spi, pins })
                 }
 
                 /// Releases the SPI peripheral and associated pins
@@ -149,38 +226,89 @@ macro_rules! hal {
                     (self.spi, self.pins)
                 }
 
-                /// Change the clock frequency of the SPI device.
-                pub fn reclock<F>(&mut self, freq: F, clocks: &Clocks) where F: Into<Hertz> {
+                /// Adds the SSInFss pin, which the SSI then drives for each
+                /// frame
+                pub fn with_fss<FSS>(self, fss: FSS) -> Spi<$SPIX, (SCK, MISO, MOSI, FSS)>
+                where
+                    FSS: FssPin<$SPIX>,
+                {
+                    Spi { spi: self.spi, pins: (self.pins.0, self.pins.1, self.pins.2, fss) }
+                }
+            }
+
+            impl<SCK, MISO, MOSI, FSS> Spi<$SPIX, (SCK, MISO, MOSI, FSS)> {
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, (SCK, MISO, MOSI, FSS)) {
+                    (self.spi, self.pins)
+                }
+            }
+
+            impl<PINS> Spi<$SPIX, PINS> {
+                /// Changes the frame format
+                pub fn set_frame_format(&mut self, format: FrameFormat) {
                     // Disable peripheral
                     self.spi.cr1.modify(|_, w| w.sse().clear_bit());
 
-                    let scr: u8;
-                    let mut cpsr = 2u32;
-                    let target_bitrate : u32 = clocks.sysclk.0 / freq.into().0;
+                    self.spi.cr0.modify(|_, w| match format {
+                        FrameFormat::Spi(mode) => {
+                            w.spo().bit(mode.polarity == Polarity::IdleHigh)
+                                .sph().bit(mode.phase == Phase::CaptureOnSecondTransition)
+                                .frf().moto()
+                        }
+                        FrameFormat::Ti => w.frf().ti(),
+                        FrameFormat::Microwire => w.frf().nmw(),
+                    });
 
-                    // Find solution for
-                    // SSInClk = SysClk / (CPSDVSR * (1 + SCR))
-                    // with:
-                    //   CPSDVSR in [2,254]
-                    //   SCR in [0,255]
+                    // Enable peripheral again
+                    self.spi.cr1.modify(|_, w| w.sse().set_bit());
+                }
 
-                    loop {
-                        let scr32 = (target_bitrate / cpsr) - 1;
-                        if scr32 < 255 {
-                            scr = scr32 as u8;
-                            break;
-                        }
-                        cpsr += 2;
-                        assert!(cpsr <= 254);
+                /// Sets the number of bits in each frame, from 4 to 16. Use
+                /// the `u16` traits for frames of more than 8 bits.
+                pub fn set_data_size(&mut self, bits: u8) -> Result<(), Error> {
+                    if !(4..=16).contains(&bits) {
+                        return Err(Error::InvalidDataSize);
                     }
 
-                    let cpsr = cpsr as u8;
+                    // Disable peripheral
+                    self.spi.cr1.modify(|_, w| w.sse().clear_bit());
+
+                    self.spi.cr0.modify(|_, w| unsafe { w.dss().bits(bits - 1) });
+
+                    // Enable peripheral again
+                    self.spi.cr1.modify(|_, w| w.sse().set_bit());
+                    Ok(())
+                }
+
+                /// Returns the number of bits in each frame
+                pub fn data_size(&self) -> u8 {
+                    self.spi.cr0.read().dss().bits() + 1
+                }
+
+                /// Changes the bit rate to the fastest not above `freq`
+                pub fn reclock<F>(&mut self, freq: F, clocks: &Clocks) -> Result<(), Error>
+                where
+                    F: Into<Hertz>,
+                {
+                    let dividers = Dividers::new(clocks.sysclk, freq.into()).map_err(Error::Clock)?;
 
-                    self.spi.cpsr.write(|w| unsafe { w.cpsdvsr().bits(cpsr) });
-                    self.spi.cr0.modify(|_,w| unsafe { w.scr().bits(scr) });
+                    // Disable peripheral
+                    self.spi.cr1.modify(|_, w| w.sse().clear_bit());
+
+                    self.spi.cpsr.write(|w| unsafe { w.cpsdvsr().bits(dividers.cpsdvsr()) });
+                    self.spi.cr0.modify(|_,w| unsafe { w.scr().bits(dividers.scr()) });
 
                     // Enable peripheral again
                     self.spi.cr1.modify(|_, w| w.sse().set_bit());
+                    Ok(())
+                }
+
+                /// Returns the bit rate
+                pub fn frequency(&self, clocks: &Clocks) -> Hertz {
+                    Dividers::from_registers(
+                        self.spi.cpsr.read().cpsdvsr().bits(),
+                        self.spi.cr0.read().scr().bits(),
+                    ).frequency(clocks.sysclk)
                 }
             }
 
@@ -214,6 +342,250 @@ macro_rules! hal {
             impl<PINS> crate::hal::blocking::spi::transfer::Default<u8> for Spi<$SPIX, PINS> {}
 
             impl<PINS> crate::hal::blocking::spi::write::Default<u8> for Spi<$SPIX, PINS> {}
+
+            impl<PINS> FullDuplex<u16> for Spi<$SPIX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u16, Error> {
+                    // Receive FIFO Not Empty
+                    if self.spi.sr.read().rne().bit_is_clear() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        Ok(self.spi.dr.read().data().bits())
+                    }
+                }
+
+                fn send(&mut self, word: u16) -> nb::Result<(), Error> {
+                    // Transmit FIFO Not Full
+                    if self.spi.sr.read().tnf().bit_is_clear() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        self.spi.dr.write(|w| unsafe {
+                            w.data().bits(word)
+                        });
+                        busy_wait!(self.spi, bsy, bit_is_clear);
+                        Ok(())
+                    }
+                }
+            }
+
+            impl<PINS> crate::hal::blocking::spi::transfer::Default<u16> for Spi<$SPIX, PINS> {}
+
+            impl<PINS> crate::hal::blocking::spi::write::Default<u16> for Spi<$SPIX, PINS> {}
+
+            impl<PINS> embedded_hal_1::spi::ErrorType for Spi<$SPIX, PINS> {
+                type Error = Error;
+            }
+
+            impl<PINS> embedded_hal_1::spi::SpiBus<u8> for Spi<$SPIX, PINS> {
+                fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    self.transfer(words, &[])
+                }
+
+                fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+                    self.transfer(&mut [], words)
+                }
+
+                fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+                    // Sends zeroes once `write` runs out, and discards what
+                    // arrives once `read` is full
+                    for i in 0..read.len().max(write.len()) {
+                        let word = write.get(i).copied().unwrap_or(0);
+                        nb::block!(FullDuplex::<u8>::send(self, word))?;
+                        let word = nb::block!(FullDuplex::<u8>::read(self))?;
+                        if let Some(r) = read.get_mut(i) {
+                            *r = word;
+                        }
+                    }
+                    Ok(())
+                }
+
+                fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    for word in words.iter_mut() {
+                        nb::block!(FullDuplex::<u8>::send(self, *word))?;
+                        *word = nb::block!(FullDuplex::<u8>::read(self))?;
+                    }
+                    Ok(())
+                }
+
+                fn flush(&mut self) -> Result<(), Error> {
+                    busy_wait!(self.spi, bsy, bit_is_clear);
+                    Ok(())
+                }
+            }
+
+            impl<PINS> embedded_hal_1::spi::SpiBus<u16> for Spi<$SPIX, PINS> {
+                fn read(&mut self, words: &mut [u16]) -> Result<(), Error> {
+                    self.transfer(words, &[])
+                }
+
+                fn write(&mut self, words: &[u16]) -> Result<(), Error> {
+                    self.transfer(&mut [], words)
+                }
+
+                fn transfer(&mut self, read: &mut [u16], write: &[u16]) -> Result<(), Error> {
+                    for i in 0..read.len().max(write.len()) {
+                        let word = write.get(i).copied().unwrap_or(0);
+                        nb::block!(FullDuplex::<u16>::send(self, word))?;
+                        let word = nb::block!(FullDuplex::<u16>::read(self))?;
+                        if let Some(r) = read.get_mut(i) {
+                            *r = word;
+                        }
+                    }
+                    Ok(())
+                }
+
+                fn transfer_in_place(&mut self, words: &mut [u16]) -> Result<(), Error> {
+                    for word in words.iter_mut() {
+                        nb::block!(FullDuplex::<u16>::send(self, *word))?;
+                        *word = nb::block!(FullDuplex::<u16>::read(self))?;
+                    }
+                    Ok(())
+                }
+
+                fn flush(&mut self) -> Result<(), Error> {
+                    busy_wait!(self.spi, bsy, bit_is_clear);
+                    Ok(())
+                }
+            }
+
+            impl<SCK, MISO, MOSI, FSS> SpiSlave<$SPIX, (SCK, MISO, MOSI, FSS)> {
+                /// Configures the SPI peripheral to operate in slave mode.
+                /// The bus clock must be no faster than a twelfth of the
+                /// system clock.
+                pub fn $spiX(
+                    spi: $SPIX,
+                    pins: (SCK, MISO, MOSI, FSS),
+                    mode: Mode,
+                    pc: &sysctl::PowerControl,
+                ) -> Self
+                where
+                    SCK: SckPin<$SPIX>,
+                    MISO: MisoPin<$SPIX>,
+                    MOSI: MosiPin<$SPIX>,
+                    FSS: FssPin<$SPIX>,
+                {
+                    // power up
+                    sysctl::control_power(
+                        pc, sysctl::Domain::$powerDomain,
+                        sysctl::RunMode::Run, sysctl::PowerState::On);
+                    sysctl::reset(pc, sysctl::Domain::$powerDomain);
+
+                    // Slave select can only be changed while disabled
+                    spi.cr1.write(|w| w.ms().set_bit());
+
+                    // The bit rate comes from the master, but the prescaler
+                    // must still be valid
+                    spi.cpsr.write(|w| unsafe {
+                        w.cpsdvsr().bits(2)
+                    });
+
+                    spi.cr0.modify(|_,w| {
+                        w.spo().bit(mode.polarity == Polarity::IdleHigh)
+                            .sph().bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .frf().moto()
+                            .dss()._8()
+                    });
+
+                    // Enable peripheral
+                    spi.cr1.modify(|_, w| w.sse().set_bit());
+
+                    SpiSlave { spi, pins }
+                }
+
+                /// Releases the SPI peripheral and associated pins. The
+                /// transmit pin is connected again if its output was
+                /// disabled.
+                pub fn free(mut self) -> ($SPIX, (SCK, MISO, MOSI, FSS))
+                where
+                    MOSI: MosiPin<$SPIX>,
+                {
+                    self.pins.2.connect(true);
+                    (self.spi, self.pins)
+                }
+
+                /// Sets whether the transmit pin drives its line, so that
+                /// this slave can listen on a bus where another slave
+                /// answers. The SSI has no slave output disable of its own,
+                /// so this disconnects the pin from the SSI, leaving it a
+                /// GPIO input.
+                pub fn set_output_enabled(&mut self, enabled: bool)
+                where
+                    MOSI: MosiPin<$SPIX>,
+                {
+                    self.pins.2.connect(enabled);
+                }
+
+                /// Queues bytes to be sent when the master next clocks the
+                /// bus, as far as they fit in the transmit FIFO. Returns how
+                /// many were queued.
+                pub fn preload(&mut self, words: &[u8]) -> usize {
+                    let mut queued = 0;
+                    for word in words {
+                        if self.spi.sr.read().tnf().bit_is_clear() {
+                            break;
+                        }
+                        self.spi.dr.write(|w| unsafe { w.data().bits((*word).into()) });
+                        queued += 1;
+                    }
+                    queued
+                }
+
+                /// Returns whether the transmit FIFO is empty
+                pub fn is_transmit_empty(&self) -> bool {
+                    self.spi.sr.read().tfe().bit_is_set()
+                }
+
+                /// Starts listening for an interrupt event
+                pub fn listen(&mut self, event: Event) {
+                    self.spi.im.modify(|r, w| unsafe { w.bits(r.bits() | event.mask()) });
+                }
+
+                /// Stops listening for an interrupt event
+                pub fn unlisten(&mut self, event: Event) {
+                    self.spi.im.modify(|r, w| unsafe { w.bits(r.bits() & !event.mask()) });
+                }
+
+                /// Returns whether an event has occurred, whether or not it
+                /// is being listened for
+                pub fn is_pending(&self, event: Event) -> bool {
+                    self.spi.ris.read().bits() & event.mask() != 0
+                }
+
+                /// Clears an interrupt event. The FIFO events can't be
+                /// cleared; they stop once the FIFO has been serviced.
+                pub fn clear_interrupt(&mut self, event: Event) {
+                    let mask = event.mask()
+                        & (Event::ReceiveOverrun.mask() | Event::ReceiveTimeout.mask());
+                    self.spi.icr.write(|w| unsafe { w.bits(mask) });
+                }
+            }
+
+            impl<PINS> FullDuplex<u8> for SpiSlave<$SPIX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    // Receive FIFO Not Empty
+                    if self.spi.sr.read().rne().bit_is_clear() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        let r = self.spi.dr.read().data().bits() as u8;
+                        Ok(r)
+                    }
+                }
+
+                fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
+                    // Transmit FIFO Not Full
+                    if self.spi.sr.read().tnf().bit_is_clear() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        self.spi.dr.write(|w| unsafe {
+                            w.data().bits(byte.into())
+                        });
+                        Ok(())
+                    }
+                }
+            }
         )+
     }
 }
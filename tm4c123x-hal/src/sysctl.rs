@@ -337,6 +337,10 @@ pub enum Domain {
     Pwm0,
     /// PWM1
     Pwm1,
+    /// QEI0
+    Qei0,
+    /// QEI1
+    Qei1,
 }
 
 /// Reset a peripheral
@@ -528,6 +532,14 @@ pub fn reset(_lock: &PowerControl, pd: Domain) {
             bb::toggle_bit(&p.srpwm, 1);
             bb::spin_bit(&p.prpwm, 1);
         },
+        Domain::Qei0 => unsafe {
+            bb::toggle_bit(&p.srqei, 0);
+            bb::spin_bit(&p.prqei, 0);
+        },
+        Domain::Qei1 => unsafe {
+            bb::toggle_bit(&p.srqei, 1);
+            bb::spin_bit(&p.prqei, 1);
+        },
     }
 }
 
@@ -605,6 +617,8 @@ fn control_run_power(pd: Domain, on: bool) {
         Domain::WideTimer0 => unsafe { bb::change_bit(&p.rcgcwtimer, 0, on) },
         Domain::Pwm0 => unsafe { bb::change_bit(&p.rcgcpwm, 0, on) },
         Domain::Pwm1 => unsafe { bb::change_bit(&p.rcgcpwm, 1, on) },
+        Domain::Qei0 => unsafe { bb::change_bit(&p.rcgcqei, 0, on) },
+        Domain::Qei1 => unsafe { bb::change_bit(&p.rcgcqei, 1, on) },
     }
 }
 
@@ -658,6 +672,8 @@ fn control_sleep_power(pd: Domain, on: bool) {
         Domain::WideTimer0 => unsafe { bb::change_bit(&p.scgcwtimer, 0, on) },
         Domain::Pwm0 => unsafe { bb::change_bit(&p.scgcpwm, 0, on) },
         Domain::Pwm1 => unsafe { bb::change_bit(&p.scgcpwm, 1, on) },
+        Domain::Qei0 => unsafe { bb::change_bit(&p.scgcqei, 0, on) },
+        Domain::Qei1 => unsafe { bb::change_bit(&p.scgcqei, 1, on) },
     }
 }
 
@@ -711,6 +727,8 @@ fn control_deep_sleep_power(pd: Domain, on: bool) {
         Domain::WideTimer0 => unsafe { bb::change_bit(&p.dcgcwtimer, 0, on) },
         Domain::Pwm0 => unsafe { bb::change_bit(&p.dcgcpwm, 0, on) },
         Domain::Pwm1 => unsafe { bb::change_bit(&p.dcgcpwm, 1, on) },
+        Domain::Qei0 => unsafe { bb::change_bit(&p.dcgcqei, 0, on) },
+        Domain::Qei1 => unsafe { bb::change_bit(&p.dcgcqei, 1, on) },
     }
 }
 
@@ -977,6 +995,34 @@ impl ClockSetup {
 
 impl PowerControl {}
 
+/// Returns the recorded reasons for the chip being reset
+pub fn reset_causes() -> ResetCauses {
+    // This is safe as it's a read
+    let p = unsafe { &*tm4c123x::SYSCTL::ptr() };
+    ResetCauses(p.resc.read().bits())
+}
+
+/// Returns the recorded reasons for the chip being reset, and clears them so
+/// that the next boot only sees the cause of the next reset
+pub fn take_reset_causes() -> ResetCauses {
+    let p = unsafe { &*tm4c123x::SYSCTL::ptr() };
+    cortex_m::interrupt::free(|_| {
+        let causes = ResetCauses(p.resc.read().bits());
+        p.resc.write(|w| unsafe { w.bits(0) });
+        causes
+    })
+}
+
+/// Resets the whole chip, as if the RST pin had been asserted (but recorded
+/// as `ResetCause::Software`). This uses SYSRESETREQ, so the core and all of
+/// the peripherals are reset.
+///
+/// There is no way to reset only the core: VECTRESET is UNPREDICTABLE
+/// outside of a debugger on the Cortex-M4.
+pub fn software_reset() -> ! {
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
 /// This module is all about identifying the physical chip we're running on.
 pub mod chip_id {
     pub use tm4c_hal::sysctl::chip_id::*;
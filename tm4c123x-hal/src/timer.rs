@@ -1,9 +1,15 @@
 //! Timers
+//!
+//! Several timers (or halves of PWM timers) can be restarted in the same
+//! clock cycle using a [`TimerSync`] group, and each timer can be told to wait
+//! for the previous timer in the daisy chain before it starts counting.
 
 use crate::{
     hal::timer::{CountDown, Periodic},
     sysctl::{self, Clocks},
+    Sealed,
 };
+use core::marker::PhantomData;
 
 #[rustfmt::skip]
 use tm4c123x::{
@@ -26,9 +32,74 @@ pub enum Event {
     TimeOut,
 }
 
+/// A timer, or one half of a PWM timer, which can be restarted as part of a
+/// [`TimerSync`] group.
+pub trait Synchronize: Sealed {
+    /// The bits to set in the GPTMSYNC register to issue a timeout event to
+    /// this timer.
+    #[doc(hidden)]
+    fn sync_mask(&self) -> u32;
+}
+
+/// A set of timers which are all restarted in the same clock cycle.
+///
+/// Triggering the group issues a timeout event to every member at once, so
+/// each one reloads from its interval load register and they all carry on in
+/// phase. Interrupts are raised as for any other timeout.
+pub struct TimerSync<'a> {
+    mask: u32,
+    _timers: PhantomData<&'a ()>,
+}
+
+impl<'a> TimerSync<'a> {
+    /// Creates an empty group.
+    ///
+    /// The GPTMSYNC register only exists in Timer 0, so this makes sure Timer
+    /// 0 is clocked. It is not reset, so Timer 0 can still be used for
+    /// something else.
+    pub fn new(pc: &sysctl::PowerControl) -> Self {
+        sysctl::control_power(
+            pc,
+            sysctl::Domain::Timer0,
+            sysctl::RunMode::Run,
+            sysctl::PowerState::On,
+        );
+        TimerSync {
+            mask: 0,
+            _timers: PhantomData,
+        }
+    }
+
+    /// Adds a timer, or one half of a PWM timer, to the group
+    pub fn with<T>(mut self, timer: &'a T) -> Self
+    where
+        T: Synchronize,
+    {
+        self.mask |= timer.sync_mask();
+        self
+    }
+
+    /// Issues a timeout event to every member of the group at the same time
+    pub fn trigger(&self) {
+        // GPTMSYNC is write-only and writing zero to a field has no effect,
+        // so this doesn't disturb whoever owns Timer 0.
+        let p = unsafe { &*TIMER0::ptr() };
+        p.sync.write(|w| unsafe { w.bits(self.mask) });
+    }
+}
+
 macro_rules! hal {
-    ($($TIM:ident: ($tim:ident, $powerDomain:ident),)+) => {
+    ($($TIM:ident: ($tim:ident, $powerDomain:ident, $sync_shift:expr),)+) => {
         $(
+            impl Sealed for Timer<$TIM> {}
+
+            impl Synchronize for Timer<$TIM> {
+                fn sync_mask(&self) -> u32 {
+                    // The timer runs concatenated, which is driven by Timer A
+                    0b01 << $sync_shift
+                }
+            }
+
             impl Periodic for Timer<$TIM> {}
 
             impl CountDown for Timer<$TIM> {
@@ -131,6 +202,28 @@ macro_rules! hal {
                     }
                 }
 
+                /// Sets whether the timer stops counting while the processor is
+                /// halted by the debugger
+                pub fn stall_on_debug(&mut self, stall: bool) {
+                    self.tim.ctl.modify(|_, w| w.tastall().bit(stall));
+                }
+
+                /// Sets whether the timer waits for a trigger before counting.
+                ///
+                /// When enabled, a running timer is re-armed and does not start
+                /// counting until the previous timer in the daisy chain (e.g.
+                /// Timer 1 for Timer 2) times out. Timer 0 is at the head of the
+                /// chain and never receives a trigger. A stopped timer stays
+                /// stopped.
+                pub fn wait_on_trigger(&mut self, enabled: bool) {
+                    let was_enabled = self.tim.ctl.read().taen().bit();
+                    // The mode register must only be changed while the timer is
+                    // disabled
+                    self.tim.ctl.modify(|_, w| w.taen().clear_bit());
+                    self.tim.tamr.modify(|_, w| w.tawot().bit(enabled));
+                    self.tim.ctl.modify(|_, w| w.taen().bit(was_enabled));
+                }
+
                 /// Releases the TIM peripheral
                 pub fn free(self) -> $TIM {
                     // pause counter
@@ -145,17 +238,17 @@ macro_rules! hal {
 }
 
 hal! {
-    TIMER0: (timer0, Timer0),
-    TIMER1: (timer1, Timer1),
-    TIMER2: (timer2, Timer2),
-    TIMER3: (timer3, Timer3),
-    TIMER4: (timer4, Timer4),
-    TIMER5: (timer5, Timer5),
-
-    WTIMER0: (wtimer0, WideTimer0),
-    WTIMER1: (wtimer1, WideTimer1),
-    WTIMER2: (wtimer2, WideTimer2),
-    WTIMER3: (wtimer3, WideTimer3),
-    WTIMER4: (wtimer4, WideTimer4),
-    WTIMER5: (wtimer5, WideTimer5),
+    TIMER0: (timer0, Timer0, 0),
+    TIMER1: (timer1, Timer1, 2),
+    TIMER2: (timer2, Timer2, 4),
+    TIMER3: (timer3, Timer3, 6),
+    TIMER4: (timer4, Timer4, 8),
+    TIMER5: (timer5, Timer5, 10),
+
+    WTIMER0: (wtimer0, WideTimer0, 12),
+    WTIMER1: (wtimer1, WideTimer1, 14),
+    WTIMER2: (wtimer2, WideTimer2, 16),
+    WTIMER3: (wtimer3, WideTimer3, 18),
+    WTIMER4: (wtimer4, WideTimer4, 20),
+    WTIMER5: (wtimer5, WideTimer5, 22),
 }
@@ -0,0 +1,26 @@
+//! Watchdog timers
+//!
+//! WDT0 is clocked from the system clock. WDT1 is clocked from PIOSC
+//! (16 MHz), so it keeps working if the main oscillator fails.
+
+use crate::{
+    bb, hal,
+    sysctl::{self, Clocks},
+    time::{Hertz, MilliSeconds},
+};
+
+use tm4c123x::{SYSCTL, WATCHDOG0, WATCHDOG1};
+
+pub use tm4c_hal::watchdog::InterruptType;
+use tm4c_hal::{watchdog::UNLOCK, watchdog_hal};
+
+/// Watchdog timer
+pub struct Watchdog<WDT> {
+    wdt: WDT,
+    clock: Hertz,
+}
+
+watchdog_hal! {
+    WATCHDOG0: (Watchdog0, 0, watchdog0, false),
+    WATCHDOG1: (Watchdog1, 1, watchdog1, true),
+}
@@ -0,0 +1,13 @@
+//! Firmware update support
+//!
+//! Use `Slots` with the `flash::Flash` driver to write and verify new images
+//! in A/B slots (giving it the running `vector_table`, so the running image
+//! is never erased), then `boot` the newest one, `jump_to` a bootloader of your
+//! own, or `enter_rom_bootloader`.
+
+pub use tm4c_hal::boot::*;
+
+use crate::rom;
+use tm4c_hal::boot_hal;
+
+boot_hal!();
@@ -0,0 +1,57 @@
+//! Analog comparators
+//!
+//! Input pins must be put in tri-state (with `into_tri_state`), which
+//! disconnects the digital input.
+
+use crate::{
+    gpio::{gpioc, gpiod, gpiol, gpiop, AlternateFunction, OutputMode, Tristate, AF5},
+    sysctl, Sealed,
+};
+use core::marker::PhantomData;
+
+use tm4c129x::COMP;
+
+pub use tm4c_hal::comp::{LadderRange, Sense};
+use tm4c_hal::{comp_hal, comp_pins};
+
+/// Comparator 0 (type state)
+pub struct C0;
+/// Comparator 1 (type state)
+pub struct C1;
+/// Comparator 2 (type state)
+pub struct C2;
+
+/// Implemented for anything that can be the positive input of a comparator
+pub trait PositiveInput<C>: Sealed {
+    /// Returns the value for the ACCTLn ASRCP field
+    #[doc(hidden)]
+    fn asrcp(&self) -> u8;
+}
+
+/// Implemented for any pin that can be the negative input of a comparator
+pub trait NegativeInput<C>: Sealed {}
+
+/// Implemented for any pin that can be the output of a comparator
+pub trait OutputPin<C>: Sealed {}
+
+comp_pins!(C0,
+    plus: [(gpioc::PC6, 0)],
+    minus: [gpioc::PC7],
+    output: [(gpiod::PD0, AF5), (gpiol::PL2, AF5)],
+);
+comp_pins!(C1,
+    plus: [(gpioc::PC5, 0), (gpioc::PC6, 1)],
+    minus: [gpioc::PC4],
+    output: [(gpiod::PD1, AF5), (gpiol::PL3, AF5)],
+);
+comp_pins!(C2,
+    plus: [(gpiop::PP0, 0), (gpioc::PC6, 1)],
+    minus: [gpiop::PP1],
+    output: [(gpiod::PD2, AF5)],
+);
+
+comp_hal!(COMP, [
+    C0: (comp0, acctl0, acstat0, 0),
+    C1: (comp1, acctl1, acstat1, 1),
+    C2: (comp2, acctl2, acstat2, 2),
+]);
@@ -0,0 +1,18 @@
+//! Code for the EEProm module.
+//!
+//! The TM4C129 has 6 KiB of EEPROM, in 96 blocks of 16 words. As well as the
+//! block and offset based traits, the driver implements `embedded_storage`'s
+//! `ReadStorage` and `Storage`, addressed in bytes from the start of the
+//! EEPROM.
+
+use core::convert::TryInto;
+
+use crate::sysctl::{self};
+use cortex_m::asm::delay;
+use tm4c129x::EEPROM;
+pub use tm4c_hal::eeprom::{
+    Blocks, Busy, EepromAddress, EepromError, Erase, Protection, Read, Write,
+};
+use tm4c_hal::eeprom_hal;
+
+eeprom_hal!(EEPROM, 96, eehide0);
@@ -26,7 +26,7 @@
 #![allow(deprecated)]
 
 pub use tm4c129x::{self, CorePeripherals, Peripherals};
-pub use tm4c_hal::{bb, delay, time};
+pub use tm4c_hal::{bb, delay, kvstore, time};
 
 // Enable use of interrupt macro
 #[cfg(feature = "rt")]
@@ -40,12 +40,20 @@ mod sealed {
     impl Sealed for () {}
 }
 
+pub mod boot;
+pub mod comp;
+pub mod eeprom;
+pub mod flash;
 pub mod gpio;
 pub mod hib;
 pub mod i2c;
+pub mod mcpwm;
 pub mod prelude;
+pub mod qei;
+pub mod rom;
 pub mod serial;
-// pub mod spi;
+pub mod spi;
 pub mod sysctl;
+pub mod watchdog;
 
 use embedded_hal as hal;
@@ -0,0 +1,41 @@
+//! Quadrature Encoder Interface (QEI)
+
+use crate::{
+    gpio::*,
+    hal::{self, Direction},
+    sysctl, Sealed,
+};
+
+use tm4c129x::QEI0;
+
+pub use tm4c_hal::qei::{CaptureMode, Config, Event, ResetMode, SignalMode, VelocityDivider};
+use tm4c_hal::{qei_hal, qei_pins};
+
+/// Quadrature encoder interface
+pub struct Qei<QEI, PINS> {
+    /// Underlying QEI peripheral
+    pub qei: QEI,
+    /// Underlying GPIO pins used by peripheral
+    pub pins: PINS,
+}
+
+/// PhA pin
+pub trait PhAPin<QEI>: Sealed {}
+
+/// PhB pin
+pub trait PhBPin<QEI>: Sealed {}
+
+/// Index pin
+pub trait IdxPin<QEI>: Sealed {}
+
+impl<QEI> IdxPin<QEI> for () {}
+
+qei_pins!(QEI0,
+    pha: [(gpiol::PL1, AF6)],
+    phb: [(gpiol::PL2, AF6)],
+    idx: [(gpiol::PL3, AF6)],
+);
+
+qei_hal! {
+    QEI0: (Qei0, qei0),
+}
@@ -1,13 +1,29 @@
 //! Serial Peripheral Interface (SPI) bus
+//!
+//! On the TM4C129 each SSI can be clocked from the system clock or from the
+//! alternate clock (ALTCLK). The alternate clock can only be used while
+//! ALTCLKCFG selects the 16 MHz PIOSC, as it does at reset.
+//!
+//! [`Qssi`] drives the advanced, bi- and quad-SPI modes used by external
+//! serial flash and displays.
+//!
+//! `Spi` also implements the embedded-hal 1.0 `SpiBus`. Wrap it in an
+//! `ExclusiveDevice`, or share it between `SharedDevice`s, to get an
+//! `SpiDevice` that drives a chip-select pin.
 
 pub use crate::hal::spi::{Mode, MODE_0, MODE_1, MODE_2, MODE_3};
+pub use tm4c_hal::spi::{ClockError, DeviceError, Dividers, ExclusiveDevice, SharedDevice};
 
 use crate::{
     gpio::{
-        gpioa::{PA2, PA4, PA5},
-        gpiob::{PB4, PB6, PB7},
-        gpiod::{PD0, PD2, PD3},
-        AlternateFunction, OutputMode, AF1, AF2,
+        gpioa::{PA2, PA3, PA4, PA5, PA6, PA7},
+        gpiob::{PB4, PB5},
+        gpiod::{PD0, PD1, PD2, PD3, PD4, PD5, PD6, PD7},
+        gpioe::{PE4, PE5},
+        gpiof::{PF0, PF1, PF2, PF3, PF4},
+        gpiop::{PP0, PP1},
+        gpioq::{PQ0, PQ1, PQ2, PQ3},
+        AlternateFunction, OutputMode, AF13, AF14, AF15,
     },
     hal::spi::{FullDuplex, Phase, Polarity},
     sysctl::{self, Clocks},
@@ -15,44 +31,243 @@ use crate::{
     Sealed,
 };
 
-use nb;
 use tm4c129x::{SSI0, SSI1, SSI2, SSI3};
 
+/// The frequency of the PIOSC, which drives the alternate clock at reset
+const PIOSC_FREQ: Hertz = Hertz(16_000_000);
+
+/// Returns the frequency of the clock that drives the bit rate
+fn input_clock(source: ClockSource, clocks: &Clocks) -> Result<Hertz, Error> {
+    match source {
+        ClockSource::System => Ok(clocks.sysclk),
+        ClockSource::Alternate => {
+            // This is safe as it's only read
+            let p = unsafe { &*tm4c129x::SYSCTL::ptr() };
+            if p.altclkcfg.read().altclk().is_piosc() {
+                Ok(PIOSC_FREQ)
+            } else {
+                Err(Error::AlternateClockNotPiosc)
+            }
+        }
+    }
+}
+
 /// SPI error
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
-    #[doc(hidden)]
-    _Extensible,
+    /// A quad width operation was given to a `Qssi` without DAT2 and DAT3
+    /// pins
+    NoQuadPins,
+    /// The data size must be between 4 and 16 bits
+    InvalidDataSize,
+    /// The bit rate can't be reached from the input clock
+    Clock(ClockError),
+    /// The alternate clock was chosen, but ALTCLKCFG doesn't select PIOSC
+    AlternateClockNotPiosc,
+}
+
+impl embedded_hal_1::spi::Error for Error {
+    fn kind(&self) -> embedded_hal_1::spi::ErrorKind {
+        embedded_hal_1::spi::ErrorKind::Other
+    }
+}
+
+/// The clock that drives the SSI baud rate generator
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockSource {
+    /// The system clock
+    System,
+    /// The alternate clock, which must be the 16 MHz PIOSC (the reset
+    /// setting of ALTCLKCFG). This keeps the bit rate fixed if the system
+    /// clock changes.
+    Alternate,
+}
+
+/// Frame format
+///
+/// Unlike the TM4C123, the TM4C129 SSI has no National Microwire format:
+/// SSICR0.FRF only supports Freescale SPI and TI.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FrameFormat {
+    /// Freescale SPI, with the given clock polarity and phase. SSInFss is
+    /// low during each frame.
+    Spi(Mode),
+    /// TI synchronous serial: SSInFss pulses high for one clock before
+    /// each frame.
+    Ti,
 }
 
 /// SCK pin
 pub trait SckPin<SPI>: Sealed {}
 
-/// MISO pin
+/// MISO pin (SSInXDAT1 in legacy mode)
 pub trait MisoPin<SPI>: Sealed {}
 
-/// MOSI pin
-pub unsafe trait MosiPin<SPI>: Sealed {}
+/// MOSI pin (SSInXDAT0 in legacy mode)
+pub trait MosiPin<SPI>: Sealed {
+    #[doc(hidden)]
+    fn connect(&mut self, connected: bool);
+}
 
 // SSI0
-impl<T> SckPin<SSI0> for PA2<AlternateFunction<AF2, T>> where T: OutputMode {}
-impl<T> MisoPin<SSI0> for PA4<AlternateFunction<AF2, T>> where T: OutputMode {}
-impl<T> MosiPin<SSI0> for PA5<AlternateFunction<AF2, T>> where T: OutputMode {}
+impl<T> SckPin<SSI0> for PA2<AlternateFunction<AF15, T>> where T: OutputMode {}
+impl<T> MosiPin<SSI0> for PA4<AlternateFunction<AF15, T>>
+where
+    T: OutputMode,
+{
+    fn connect(&mut self, connected: bool) {
+        self.set_connected(connected)
+    }
+}
+impl<T> MisoPin<SSI0> for PA5<AlternateFunction<AF15, T>> where T: OutputMode {}
 
 // SSI1
-impl<T> SckPin<SSI1> for PD0<AlternateFunction<AF2, T>> where T: OutputMode {}
-impl<T> MisoPin<SSI1> for PD2<AlternateFunction<AF2, T>> where T: OutputMode {}
-impl<T> MosiPin<SSI1> for PD3<AlternateFunction<AF2, T>> where T: OutputMode {}
+impl<T> SckPin<SSI1> for PB5<AlternateFunction<AF15, T>> where T: OutputMode {}
+impl<T> MosiPin<SSI1> for PE4<AlternateFunction<AF15, T>>
+where
+    T: OutputMode,
+{
+    fn connect(&mut self, connected: bool) {
+        self.set_connected(connected)
+    }
+}
+impl<T> MisoPin<SSI1> for PE5<AlternateFunction<AF15, T>> where T: OutputMode {}
 
 // SSI2
-impl<T> SckPin<SSI2> for PB4<AlternateFunction<AF2, T>> where T: OutputMode {}
-impl<T> MisoPin<SSI2> for PB6<AlternateFunction<AF2, T>> where T: OutputMode {}
-impl<T> MosiPin<SSI2> for PB7<AlternateFunction<AF2, T>> where T: OutputMode {}
+impl<T> SckPin<SSI2> for PD3<AlternateFunction<AF15, T>> where T: OutputMode {}
+impl<T> MosiPin<SSI2> for PD1<AlternateFunction<AF15, T>>
+where
+    T: OutputMode,
+{
+    fn connect(&mut self, connected: bool) {
+        self.set_connected(connected)
+    }
+}
+impl<T> MisoPin<SSI2> for PD0<AlternateFunction<AF15, T>> where T: OutputMode {}
 
 // SSI3
-impl<T> SckPin<SSI3> for PD0<AlternateFunction<AF1, T>> where T: OutputMode {}
-impl<T> MisoPin<SSI3> for PD2<AlternateFunction<AF1, T>> where T: OutputMode {}
-impl<T> MosiPin<SSI3> for PD3<AlternateFunction<AF1, T>> where T: OutputMode {}
+impl<T> SckPin<SSI3> for PQ0<AlternateFunction<AF14, T>> where T: OutputMode {}
+impl<T> MosiPin<SSI3> for PQ2<AlternateFunction<AF14, T>>
+where
+    T: OutputMode,
+{
+    fn connect(&mut self, connected: bool) {
+        self.set_connected(connected)
+    }
+}
+impl<T> MisoPin<SSI3> for PQ3<AlternateFunction<AF14, T>> where T: OutputMode {}
+impl<T> SckPin<SSI3> for PF3<AlternateFunction<AF14, T>> where T: OutputMode {}
+impl<T> MosiPin<SSI3> for PF1<AlternateFunction<AF14, T>>
+where
+    T: OutputMode,
+{
+    fn connect(&mut self, connected: bool) {
+        self.set_connected(connected)
+    }
+}
+impl<T> MisoPin<SSI3> for PF0<AlternateFunction<AF14, T>> where T: OutputMode {}
+
+/// Frame select pin (SSInFss)
+pub trait FssPin<SPI>: Sealed {}
+
+/// SSInXDAT2 pin, used in quad mode
+pub trait Dat2Pin<SPI>: Sealed {}
+
+/// SSInXDAT3 pin, used in quad mode
+pub trait Dat3Pin<SPI>: Sealed {}
+
+/// The quad mode data pins of a `Qssi`: `()` if only single and bi-SPI
+/// are used, otherwise `(DAT2, DAT3)`.
+pub trait QuadPins<SPI> {
+    #[doc(hidden)]
+    fn quad() -> bool;
+}
+
+impl<SPI> QuadPins<SPI> for () {
+    fn quad() -> bool {
+        false
+    }
+}
+
+impl<SPI, DAT2, DAT3> QuadPins<SPI> for (DAT2, DAT3)
+where
+    DAT2: Dat2Pin<SPI>,
+    DAT3: Dat3Pin<SPI>,
+{
+    fn quad() -> bool {
+        true
+    }
+}
+
+// SSI0
+impl<T> FssPin<SSI0> for PA3<AlternateFunction<AF15, T>> where T: OutputMode {}
+impl<T> Dat2Pin<SSI0> for PA6<AlternateFunction<AF13, T>> where T: OutputMode {}
+impl<T> Dat3Pin<SSI0> for PA7<AlternateFunction<AF13, T>> where T: OutputMode {}
+
+// SSI1
+impl<T> FssPin<SSI1> for PB4<AlternateFunction<AF15, T>> where T: OutputMode {}
+impl<T> Dat2Pin<SSI1> for PD4<AlternateFunction<AF15, T>> where T: OutputMode {}
+impl<T> Dat3Pin<SSI1> for PD5<AlternateFunction<AF15, T>> where T: OutputMode {}
+
+// SSI2
+impl<T> FssPin<SSI2> for PD2<AlternateFunction<AF15, T>> where T: OutputMode {}
+impl<T> Dat2Pin<SSI2> for PD7<AlternateFunction<AF15, T>> where T: OutputMode {}
+impl<T> Dat3Pin<SSI2> for PD6<AlternateFunction<AF15, T>> where T: OutputMode {}
+
+// SSI3
+impl<T> FssPin<SSI3> for PQ1<AlternateFunction<AF14, T>> where T: OutputMode {}
+impl<T> Dat2Pin<SSI3> for PP0<AlternateFunction<AF15, T>> where T: OutputMode {}
+impl<T> Dat3Pin<SSI3> for PP1<AlternateFunction<AF15, T>> where T: OutputMode {}
+impl<T> FssPin<SSI3> for PF2<AlternateFunction<AF14, T>> where T: OutputMode {}
+impl<T> Dat2Pin<SSI3> for PF4<AlternateFunction<AF14, T>> where T: OutputMode {}
+
+/// The number of data lines used by one part of a `Qssi` transaction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Width {
+    /// One line in each direction (SSInXDAT0 out, SSInXDAT1 in)
+    Single,
+    /// Bi-SPI, on SSInXDAT0 and SSInXDAT1
+    Dual,
+    /// Quad-SPI, on SSInXDAT0 to SSInXDAT3
+    Quad,
+}
+
+/// One part of a `Qssi` transaction
+#[derive(Debug)]
+pub enum Operation<'a> {
+    /// Sends the bytes, discarding anything received
+    Write(Width, &'a [u8]),
+    /// Fills the buffer. In single width, 0xFF is sent meanwhile.
+    Read(Width, &'a mut [u8]),
+    /// Sends the bytes in single width and replaces them with those received
+    Transfer(&'a mut [u8]),
+}
+
+impl Operation<'_> {
+    fn width(&self) -> Width {
+        match self {
+            Operation::Write(width, _) | Operation::Read(width, _) => *width,
+            Operation::Transfer(_) => Width::Single,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Operation::Write(_, words) => words.len(),
+            Operation::Read(_, words) | Operation::Transfer(words) => words.len(),
+        }
+    }
+}
+
+/// SSI peripheral operating in advanced, bi- or quad-SPI master mode
+///
+/// SSInFss is held asserted for a whole transaction, so the command,
+/// address and data of a serial flash access can each use their own width.
+pub struct Qssi<SPI, PINS> {
+    spi: SPI,
+    pins: PINS,
+}
 
 /// SPI peripheral operating in full duplex master mode
 pub struct Spi<SPI, PINS> {
@@ -60,6 +275,36 @@ pub struct Spi<SPI, PINS> {
     pins: PINS,
 }
 
+/// SPI peripheral operating in slave mode
+///
+/// The SSI's transmit pin (`MosiPin`) drives the bus's MISO line and its
+/// receive pin (`MisoPin`) reads MOSI.
+pub struct SpiSlave<SPI, PINS> {
+    spi: SPI,
+    pins: PINS,
+}
+
+/// Interrupt events
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// The receive FIFO was full when a frame arrived, so it was lost
+    ReceiveOverrun,
+    /// The receive FIFO has not been read for 32 bit periods since the
+    /// last frame arrived
+    ReceiveTimeout,
+    /// The receive FIFO is half full or more
+    Receive,
+    /// The transmit FIFO is half full or less
+    Transmit,
+}
+
+impl Event {
+    /// Returns the bit for this event in the interrupt registers
+    pub fn mask(self) -> u32 {
+        1 << (self as u32)
+    }
+}
+
 macro_rules! busy_wait {
     ($spi:expr, $flag:ident, $op:ident) => {
         loop {
@@ -72,10 +317,11 @@ macro_rules! busy_wait {
 }
 
 macro_rules! hal {
-    ($($SPIX:ident: ($powerDomain:ident, $spiX:ident),)+) => {
+    ($($SPIX:ident: ($powerDomain:ident, $spiX:ident, $qssiX:ident),)+) => {
         $(
             impl<SCK, MISO, MOSI> Spi<$SPIX, (SCK, MISO, MOSI)> {
-                /// Configures the SPI peripheral to operate in full duplex master mode
+                /// Configures the SPI peripheral to operate in full duplex
+                /// master mode, at the fastest bit rate not above `freq`
                 pub fn $spiX<F>(
                     spi: $SPIX,
                     pins: (SCK, MISO, MOSI),
@@ -83,13 +329,15 @@ macro_rules! hal {
                     freq: F,
                     clocks: &Clocks,
                     pc: &sysctl::PowerControl,
-                ) -> Self
+                ) -> Result<Self, Error>
                 where
                     F: Into<Hertz>,
                     SCK: SckPin<$SPIX>,
                     MISO: MisoPin<$SPIX>,
                     MOSI: MosiPin<$SPIX>,
                 {
+                    let dividers = Dividers::new(clocks.sysclk, freq.into()).map_err(Error::Clock)?;
+
                     // power up
                     sysctl::control_power(
                         pc, sysctl::Domain::$powerDomain,
@@ -101,54 +349,148 @@ macro_rules! hal {
 
                     // SSICC Clock setup
                     // set to reset value (0 = use system clock)
-                    spi.cc.write(|w| w);
-
-                    // Use Moto/SPI & 8bits data size
-                    let scr: u8;
-                    let mut cpsr = 2u32;
-                    let target_bitrate : u32 = clocks.sysclk.0 / freq.into().0;
-
-                    // Find solution for
-                    // SSInClk = SysClk / (CPSDVSR * (1 + SCR))
-                    // with:
-                    //   CPSDVSR in [2,254]
-                    //   SCR in [0,255]
-
-                    loop {
-                        let scr32 = (target_bitrate / cpsr) - 1;
-                        if scr32 < 255 {
-                            scr = scr32 as u8;
-                            break;
-                        }
-                        cpsr += 2;
-                        assert!(cpsr <= 254);
-                    }
-
-                    let cpsr = cpsr as u8;
+                    spi.cc.write(|w| w.cs().syspll());
 
                     spi.cpsr.write(|w| unsafe {
-                        w.cpsdvsr().bits(cpsr)
+                        w.cpsdvsr().bits(dividers.cpsdvsr())
                     });
 
+                    // Use Moto/SPI & 8bits data size
                     spi.cr0.modify(|_,w| unsafe {
                         w.spo().bit(mode.polarity == Polarity::IdleHigh)
                             .sph().bit(mode.phase == Phase::CaptureOnSecondTransition)
-                            // FIXME: How to use FRFR::MOTO and DSS:: ?
-                            .frf().bits(0)
-                            .dss().bits(0x7)
-                            .scr().bits(scr)
+                            .frf().moto()
+                            .dss()._8()
+                            .scr().bits(dividers.scr())
                     });
 
                     // Enable peripheral
                     spi.cr1.write(|w| w.sse().set_bit());
 
-                    Spi { spi, pins }
+                    Ok(Spi { spi, pins })
                 }
 
                 /// Releases the SPI peripheral and associated pins
                 pub fn free(self) -> ($SPIX, (SCK, MISO, MOSI)) {
                     (self.spi, self.pins)
                 }
+
+                /// Adds the SSInFss pin, which the SSI then drives for each
+                /// frame
+                pub fn with_fss<FSS>(self, fss: FSS) -> Spi<$SPIX, (SCK, MISO, MOSI, FSS)>
+                where
+                    FSS: FssPin<$SPIX>,
+                {
+                    Spi { spi: self.spi, pins: (self.pins.0, self.pins.1, self.pins.2, fss) }
+                }
+            }
+
+            impl<SCK, MISO, MOSI, FSS> Spi<$SPIX, (SCK, MISO, MOSI, FSS)> {
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, (SCK, MISO, MOSI, FSS)) {
+                    (self.spi, self.pins)
+                }
+            }
+
+            impl<PINS> Spi<$SPIX, PINS> {
+                /// Changes the frame format
+                pub fn set_frame_format(&mut self, format: FrameFormat) {
+                    // Disable peripheral
+                    self.spi.cr1.modify(|_, w| w.sse().clear_bit());
+
+                    self.spi.cr0.modify(|_, w| match format {
+                        FrameFormat::Spi(mode) => {
+                            w.spo().bit(mode.polarity == Polarity::IdleHigh)
+                                .sph().bit(mode.phase == Phase::CaptureOnSecondTransition)
+                                .frf().moto()
+                        }
+                        FrameFormat::Ti => w.frf().ti(),
+                    });
+
+                    // Enable peripheral again
+                    self.spi.cr1.modify(|_, w| w.sse().set_bit());
+                }
+
+                /// Sets the number of bits in each frame, from 4 to 16. Use
+                /// the `u16` traits for frames of more than 8 bits.
+                pub fn set_data_size(&mut self, bits: u8) -> Result<(), Error> {
+                    if !(4..=16).contains(&bits) {
+                        return Err(Error::InvalidDataSize);
+                    }
+
+                    // Disable peripheral
+                    self.spi.cr1.modify(|_, w| w.sse().clear_bit());
+
+                    self.spi.cr0.modify(|_, w| unsafe { w.dss().bits(bits - 1) });
+
+                    // Enable peripheral again
+                    self.spi.cr1.modify(|_, w| w.sse().set_bit());
+                    Ok(())
+                }
+
+                /// Returns the number of bits in each frame
+                pub fn data_size(&self) -> u8 {
+                    self.spi.cr0.read().dss().bits() + 1
+                }
+
+                /// Selects the clock that drives the bit rate and sets the
+                /// bit rate again from it. The alternate clock is rejected
+                /// unless ALTCLKCFG selects PIOSC.
+                pub fn set_clock_source<F>(
+                    &mut self,
+                    source: ClockSource,
+                    freq: F,
+                    clocks: &Clocks,
+                ) -> Result<(), Error>
+                where
+                    F: Into<Hertz>,
+                {
+                    let input = input_clock(source, clocks)?;
+                    let dividers = Dividers::new(input, freq.into()).map_err(Error::Clock)?;
+
+                    // Disable peripheral
+                    self.spi.cr1.modify(|_, w| w.sse().clear_bit());
+
+                    match source {
+                        ClockSource::System => self.spi.cc.write(|w| w.cs().syspll()),
+                        ClockSource::Alternate => self.spi.cc.write(|w| w.cs().piosc()),
+                    }
+                    self.spi.cpsr.write(|w| unsafe { w.cpsdvsr().bits(dividers.cpsdvsr()) });
+                    self.spi.cr0.modify(|_,w| unsafe { w.scr().bits(dividers.scr()) });
+
+                    // Enable peripheral again
+                    self.spi.cr1.modify(|_, w| w.sse().set_bit());
+                    Ok(())
+                }
+
+                /// Changes the bit rate to the fastest not above `freq`,
+                /// keeping the clock source
+                pub fn reclock<F>(&mut self, freq: F, clocks: &Clocks) -> Result<(), Error>
+                where
+                    F: Into<Hertz>,
+                {
+                    let source = self.clock_source();
+                    self.set_clock_source(source, freq, clocks)
+                }
+
+                /// Returns the bit rate, or an error if the alternate clock
+                /// is used and ALTCLKCFG no longer selects PIOSC
+                pub fn frequency(&self, clocks: &Clocks) -> Result<Hertz, Error> {
+                    let input = input_clock(self.clock_source(), clocks)?;
+                    Ok(Dividers::from_registers(
+                        self.spi.cpsr.read().cpsdvsr().bits(),
+                        self.spi.cr0.read().scr().bits(),
+                    ).frequency(input))
+                }
+
+                /// Returns the clock that drives the bit rate
+                pub fn clock_source(&self) -> ClockSource {
+                    if self.spi.cc.read().cs().is_piosc() {
+                        ClockSource::Alternate
+                    } else {
+                        ClockSource::System
+                    }
+                }
             }
 
             impl<PINS> FullDuplex<u8> for Spi<$SPIX, PINS> {
@@ -181,13 +523,400 @@ macro_rules! hal {
             impl<PINS> crate::hal::blocking::spi::transfer::Default<u8> for Spi<$SPIX, PINS> {}
 
             impl<PINS> crate::hal::blocking::spi::write::Default<u8> for Spi<$SPIX, PINS> {}
+
+            impl<PINS> FullDuplex<u16> for Spi<$SPIX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u16, Error> {
+                    // Receive FIFO Not Empty
+                    if self.spi.sr.read().rne().bit_is_clear() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        Ok(self.spi.dr.read().data().bits())
+                    }
+                }
+
+                fn send(&mut self, word: u16) -> nb::Result<(), Error> {
+                    // Transmit FIFO Not Full
+                    if self.spi.sr.read().tnf().bit_is_clear() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        self.spi.dr.write(|w| unsafe {
+                            w.data().bits(word)
+                        });
+                        busy_wait!(self.spi, bsy, bit_is_clear);
+                        Ok(())
+                    }
+                }
+            }
+
+            impl<PINS> crate::hal::blocking::spi::transfer::Default<u16> for Spi<$SPIX, PINS> {}
+
+            impl<PINS> crate::hal::blocking::spi::write::Default<u16> for Spi<$SPIX, PINS> {}
+
+            impl<PINS> embedded_hal_1::spi::ErrorType for Spi<$SPIX, PINS> {
+                type Error = Error;
+            }
+
+            impl<PINS> embedded_hal_1::spi::SpiBus<u8> for Spi<$SPIX, PINS> {
+                fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    self.transfer(words, &[])
+                }
+
+                fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+                    self.transfer(&mut [], words)
+                }
+
+                fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+                    // Sends zeroes once `write` runs out, and discards what
+                    // arrives once `read` is full
+                    for i in 0..read.len().max(write.len()) {
+                        let word = write.get(i).copied().unwrap_or(0);
+                        nb::block!(FullDuplex::<u8>::send(self, word))?;
+                        let word = nb::block!(FullDuplex::<u8>::read(self))?;
+                        if let Some(r) = read.get_mut(i) {
+                            *r = word;
+                        }
+                    }
+                    Ok(())
+                }
+
+                fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    for word in words.iter_mut() {
+                        nb::block!(FullDuplex::<u8>::send(self, *word))?;
+                        *word = nb::block!(FullDuplex::<u8>::read(self))?;
+                    }
+                    Ok(())
+                }
+
+                fn flush(&mut self) -> Result<(), Error> {
+                    busy_wait!(self.spi, bsy, bit_is_clear);
+                    Ok(())
+                }
+            }
+
+            impl<PINS> embedded_hal_1::spi::SpiBus<u16> for Spi<$SPIX, PINS> {
+                fn read(&mut self, words: &mut [u16]) -> Result<(), Error> {
+                    self.transfer(words, &[])
+                }
+
+                fn write(&mut self, words: &[u16]) -> Result<(), Error> {
+                    self.transfer(&mut [], words)
+                }
+
+                fn transfer(&mut self, read: &mut [u16], write: &[u16]) -> Result<(), Error> {
+                    for i in 0..read.len().max(write.len()) {
+                        let word = write.get(i).copied().unwrap_or(0);
+                        nb::block!(FullDuplex::<u16>::send(self, word))?;
+                        let word = nb::block!(FullDuplex::<u16>::read(self))?;
+                        if let Some(r) = read.get_mut(i) {
+                            *r = word;
+                        }
+                    }
+                    Ok(())
+                }
+
+                fn transfer_in_place(&mut self, words: &mut [u16]) -> Result<(), Error> {
+                    for word in words.iter_mut() {
+                        nb::block!(FullDuplex::<u16>::send(self, *word))?;
+                        *word = nb::block!(FullDuplex::<u16>::read(self))?;
+                    }
+                    Ok(())
+                }
+
+                fn flush(&mut self) -> Result<(), Error> {
+                    busy_wait!(self.spi, bsy, bit_is_clear);
+                    Ok(())
+                }
+            }
+
+            impl<SCK, MISO, MOSI, FSS> SpiSlave<$SPIX, (SCK, MISO, MOSI, FSS)> {
+                /// Configures the SPI peripheral to operate in slave mode.
+                /// The bus clock must be no faster than a twelfth of the
+                /// system clock.
+                pub fn $spiX(
+                    spi: $SPIX,
+                    pins: (SCK, MISO, MOSI, FSS),
+                    mode: Mode,
+                    pc: &sysctl::PowerControl,
+                ) -> Self
+                where
+                    SCK: SckPin<$SPIX>,
+                    MISO: MisoPin<$SPIX>,
+                    MOSI: MosiPin<$SPIX>,
+                    FSS: FssPin<$SPIX>,
+                {
+                    // power up
+                    sysctl::control_power(
+                        pc, sysctl::Domain::$powerDomain,
+                        sysctl::RunMode::Run, sysctl::PowerState::On);
+                    sysctl::reset(pc, sysctl::Domain::$powerDomain);
+
+                    // Slave select can only be changed while disabled
+                    spi.cr1.write(|w| w.ms().set_bit());
+
+                    // The bit rate comes from the master, but the prescaler
+                    // must still be valid
+                    spi.cpsr.write(|w| unsafe {
+                        w.cpsdvsr().bits(2)
+                    });
+
+                    spi.cr0.modify(|_,w| {
+                        w.spo().bit(mode.polarity == Polarity::IdleHigh)
+                            .sph().bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .frf().moto()
+                            .dss()._8()
+                    });
+
+                    // Enable peripheral
+                    spi.cr1.modify(|_, w| w.sse().set_bit());
+
+                    SpiSlave { spi, pins }
+                }
+
+                /// Releases the SPI peripheral and associated pins. The
+                /// transmit pin is connected again if its output was
+                /// disabled.
+                pub fn free(mut self) -> ($SPIX, (SCK, MISO, MOSI, FSS))
+                where
+                    MOSI: MosiPin<$SPIX>,
+                {
+                    self.pins.2.connect(true);
+                    (self.spi, self.pins)
+                }
+
+                /// Sets whether the transmit pin drives its line, so that
+                /// this slave can listen on a bus where another slave
+                /// answers. The SSI has no slave output disable of its own,
+                /// so this disconnects the pin from the SSI, leaving it a
+                /// GPIO input.
+                pub fn set_output_enabled(&mut self, enabled: bool)
+                where
+                    MOSI: MosiPin<$SPIX>,
+                {
+                    self.pins.2.connect(enabled);
+                }
+
+                /// Queues bytes to be sent when the master next clocks the
+                /// bus, as far as they fit in the transmit FIFO. Returns how
+                /// many were queued.
+                pub fn preload(&mut self, words: &[u8]) -> usize {
+                    let mut queued = 0;
+                    for word in words {
+                        if self.spi.sr.read().tnf().bit_is_clear() {
+                            break;
+                        }
+                        self.spi.dr.write(|w| unsafe { w.data().bits((*word).into()) });
+                        queued += 1;
+                    }
+                    queued
+                }
+
+                /// Returns whether the transmit FIFO is empty
+                pub fn is_transmit_empty(&self) -> bool {
+                    self.spi.sr.read().tfe().bit_is_set()
+                }
+
+                /// Starts listening for an interrupt event
+                pub fn listen(&mut self, event: Event) {
+                    self.spi.im.modify(|r, w| unsafe { w.bits(r.bits() | event.mask()) });
+                }
+
+                /// Stops listening for an interrupt event
+                pub fn unlisten(&mut self, event: Event) {
+                    self.spi.im.modify(|r, w| unsafe { w.bits(r.bits() & !event.mask()) });
+                }
+
+                /// Returns whether an event has occurred, whether or not it
+                /// is being listened for
+                pub fn is_pending(&self, event: Event) -> bool {
+                    self.spi.ris.read().bits() & event.mask() != 0
+                }
+
+                /// Clears an interrupt event. The FIFO events can't be
+                /// cleared; they stop once the FIFO has been serviced.
+                pub fn clear_interrupt(&mut self, event: Event) {
+                    let mask = event.mask()
+                        & (Event::ReceiveOverrun.mask() | Event::ReceiveTimeout.mask());
+                    self.spi.icr.write(|w| unsafe { w.bits(mask) });
+                }
+            }
+
+            impl<PINS> FullDuplex<u8> for SpiSlave<$SPIX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    // Receive FIFO Not Empty
+                    if self.spi.sr.read().rne().bit_is_clear() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        let r = self.spi.dr.read().data().bits() as u8;
+                        Ok(r)
+                    }
+                }
+
+                fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
+                    // Transmit FIFO Not Full
+                    if self.spi.sr.read().tnf().bit_is_clear() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        self.spi.dr.write(|w| unsafe {
+                            w.data().bits(byte.into())
+                        });
+                        Ok(())
+                    }
+                }
+            }
+
+            impl<SCK, DAT1, DAT0, FSS, QUAD> Qssi<$SPIX, (SCK, DAT1, DAT0, FSS, QUAD)>
+            where
+                QUAD: QuadPins<$SPIX>,
+            {
+                /// Configures the SSI to operate in advanced master mode,
+                /// with 8-bit frames and SSInFss held across a transaction,
+                /// at the fastest bit rate not above `freq`. The pins are
+                /// in the same order as for `Spi::with_fss`, with the
+                /// SSInXDAT2 and SSInXDAT3 pins (or `()`) last.
+                pub fn $qssiX<F>(
+                    spi: $SPIX,
+                    pins: (SCK, DAT1, DAT0, FSS, QUAD),
+                    mode: Mode,
+                    freq: F,
+                    clocks: &Clocks,
+                    pc: &sysctl::PowerControl,
+                ) -> Result<Self, Error>
+                where
+                    F: Into<Hertz>,
+                    SCK: SckPin<$SPIX>,
+                    DAT1: MisoPin<$SPIX>,
+                    DAT0: MosiPin<$SPIX>,
+                    FSS: FssPin<$SPIX>,
+                {
+                    let dividers = Dividers::new(clocks.sysclk, freq.into()).map_err(Error::Clock)?;
+
+                    // power up
+                    sysctl::control_power(
+                        pc, sysctl::Domain::$powerDomain,
+                        sysctl::RunMode::Run, sysctl::PowerState::On);
+                    sysctl::reset(pc, sysctl::Domain::$powerDomain);
+
+                    spi.cr1.write(|w| w);
+                    spi.cc.write(|w| w.cs().syspll());
+
+                    spi.cpsr.write(|w| unsafe {
+                        w.cpsdvsr().bits(dividers.cpsdvsr())
+                    });
+
+                    // The advanced modes only support 8-bit Freescale frames
+                    spi.cr0.modify(|_,w| unsafe {
+                        w.spo().bit(mode.polarity == Polarity::IdleHigh)
+                            .sph().bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .frf().moto()
+                            .dss()._8()
+                            .scr().bits(dividers.scr())
+                    });
+
+                    // SSInFss stays asserted until a byte written with EOM set
+                    spi.cr1.write(|w| {
+                        w.mode().advanced()
+                            .fsshldfrm().set_bit()
+                            .sse().set_bit()
+                    });
+
+                    Ok(Qssi { spi, pins })
+                }
+
+                /// Releases the SSI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, (SCK, DAT1, DAT0, FSS, QUAD)) {
+                    (self.spi, self.pins)
+                }
+
+                /// Runs the operations in order as one frame, with SSInFss
+                /// asserted throughout.
+                pub fn transaction(&mut self, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+                    if !QUAD::quad() && operations.iter().any(|op| op.width() == Width::Quad) {
+                        return Err(Error::NoQuadPins);
+                    }
+
+                    // The last byte of the transaction releases SSInFss
+                    let last = operations.iter().rposition(|op| op.len() != 0);
+
+                    for (i, op) in operations.iter_mut().enumerate() {
+                        let end = Some(i) == last;
+                        match op {
+                            Operation::Write(width, words) => {
+                                self.set_mode(*width, false);
+                                for (n, word) in words.iter().enumerate() {
+                                    self.put(*word, end && n + 1 == words.len());
+                                }
+                            }
+                            Operation::Read(width, words) => {
+                                self.set_mode(*width, true);
+                                let len = words.len();
+                                for (n, word) in words.iter_mut().enumerate() {
+                                    self.put(0xFF, end && n + 1 == len);
+                                    *word = self.get();
+                                }
+                            }
+                            Operation::Transfer(words) => {
+                                self.set_mode(Width::Single, true);
+                                let len = words.len();
+                                for (n, word) in words.iter_mut().enumerate() {
+                                    self.put(*word, end && n + 1 == len);
+                                    *word = self.get();
+                                }
+                            }
+                        }
+                    }
+
+                    self.flush();
+                    Ok(())
+                }
+
+                /// Waits for the bus to go idle and empties the receive FIFO
+                fn flush(&mut self) {
+                    busy_wait!(self.spi, tfe, bit_is_set);
+                    busy_wait!(self.spi, bsy, bit_is_clear);
+                    while self.spi.sr.read().rne().bit_is_set() {
+                        self.spi.dr.read();
+                    }
+                }
+
+                /// Changes the frame mode between operations. The
+                /// direction only matters in bi and quad mode; in advanced
+                /// mode it selects full duplex.
+                fn set_mode(&mut self, width: Width, read: bool) {
+                    self.flush();
+                    self.spi.cr1.modify(|_, w| {
+                        match width {
+                            Width::Single => w.mode().advanced(),
+                            Width::Dual => w.mode().bi(),
+                            Width::Quad => w.mode().quad(),
+                        }
+                        .dir().bit(read)
+                    });
+                }
+
+                fn put(&mut self, word: u8, end: bool) {
+                    busy_wait!(self.spi, tnf, bit_is_set);
+                    if end {
+                        self.spi.cr1.modify(|_, w| w.eom().set_bit());
+                    }
+                    self.spi.dr.write(|w| unsafe { w.data().bits(word.into()) });
+                }
+
+                fn get(&mut self) -> u8 {
+                    busy_wait!(self.spi, rne, bit_is_set);
+                    self.spi.dr.read().data().bits() as u8
+                }
+            }
         )+
     }
 }
 
 hal! {
-    SSI0: (Ssi0, spi0),
-    SSI1: (Ssi1, spi1),
-    SSI2: (Ssi2, spi2),
-    SSI3: (Ssi3, spi3),
+    SSI0: (Ssi0, spi0, qssi0),
+    SSI1: (Ssi1, spi1, qssi1),
+    SSI2: (Ssi2, spi2, qssi2),
+    SSI3: (Ssi3, spi3, qssi3),
 }